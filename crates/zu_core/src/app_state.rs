@@ -1,8 +1,16 @@
+use crate::brush::tools::{ToolAction, ToolPalette};
 use crate::camera::Camera;
 use crate::egui_tools::EguiRenderer;
-use crate::gui::EngineGui;
-
-use crate::render_passes::render_pass_manager::RenderPassManager;
+use crate::gui::{EngineGui, ReferenceControls, SceneInput};
+use crate::history::History;
+use crate::layers::LayerStack;
+use crate::lights::Light;
+use crate::render_passes::lit_sprite_pass::LitSprite;
+use crate::render_passes::object_render_pass::SceneObject;
+
+use crate::render_passes::render_pass_manager::{
+    CanvasAnchor, CanvasSettings, RadianceReadback, ReferenceReadback, RenderPassManager,
+};
 use crate::styles::default_dark::default_dark_theme;
 use egui_wgpu::wgpu::SurfaceError;
 use egui_wgpu::{ScreenDescriptor, wgpu};
@@ -17,6 +25,10 @@ use winit::event::WindowEvent;
 use winit::platform::web::WindowExtWebSys;
 use winit::window::Window;
 
+/// Where "Save reference" writes the reference radiance.
+#[cfg(not(target_arch = "wasm32"))]
+const REFERENCE_PATH: &str = "reference.pfm";
+
 pub struct AppState {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
@@ -27,10 +39,16 @@ pub struct AppState {
     pub engine_gui: EngineGui,
     pub window: Arc<Window>,
     pub camera: Camera,
-    color: [f32; 4],
-    paint: bool,
-    mouse_pos: Vec2,
-    brush_radius: u32,
+    /// Drawn into the scene every frame, e.g. the moving parts of a game.
+    pub objects: Vec<SceneObject>,
+    /// Drawn over the lit scene every frame, shaded by the light around them.
+    pub lit_sprites: Vec<LitSprite>,
+    tools: ToolPalette,
+    history: History,
+    layers: LayerStack,
+    canvas: CanvasSettings,
+    scene_input: SceneInput,
+    reference: ReferenceControls,
     render_pass_manager: RenderPassManager,
     present_mode: PresentMode,
     vsync_enabled: bool,
@@ -142,7 +160,8 @@ impl AppState {
         let camera =
             Camera::from_screen_size(width as f32, height as f32, 0.1, 1000.0, 1.0, Vec2::ZERO);
 
-        let render_pass_manager = RenderPassManager::new(&device, &surface_config, width, height);
+        let render_pass_manager =
+            RenderPassManager::new(&device, &queue, &surface_config, width, height);
 
         info!("App State created!!");
 
@@ -157,10 +176,18 @@ impl AppState {
             window,
             render_pass_manager,
             camera,
-            paint: false,
-            color: [1.0, 1.0, 1.0, 1.0],
-            mouse_pos: Vec2::ZERO,
-            brush_radius: 10,
+            objects: Vec::new(),
+            lit_sprites: Vec::new(),
+            tools: Default::default(),
+            history: Default::default(),
+            layers: Default::default(),
+            canvas: CanvasSettings {
+                width,
+                height,
+                anchor: CanvasAnchor::TopLeft,
+            },
+            scene_input: Default::default(),
+            reference: Default::default(),
             present_mode: wgpu::PresentMode::AutoVsync,
             vsync_enabled: true,
             instance,
@@ -174,8 +201,11 @@ impl AppState {
         self.surface.configure(&self.device, &self.surface_config);
         self.camera
             .update_from_screen_size(width as f32, height as f32);
-        self.render_pass_manager
-            .resize(width, height, &self.device, &self.queue);
+    }
+
+    /// Lights of the world, in world units.
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        self.render_pass_manager.lights_mut()
     }
 
     pub fn set_vsync_enabled(&mut self, enabled: bool) {
@@ -203,17 +233,43 @@ impl AppState {
         let width = self.surface_config.width;
         let height = self.surface_config.height;
 
-        if self.paint {
-            self.render_pass_manager.paint(
-                self.mouse_pos,
-                self.color,
-                self.brush_radius,
-                width,
-                height,
+        if self.render_pass_manager.canvas_size() != (self.canvas.width, self.canvas.height) {
+            self.render_pass_manager.resize_canvas(
+                self.canvas,
+                &self.device,
                 &self.queue,
+                &self.layers,
             );
         }
 
+        self.camera.pixels_per_point = self.window.scale_factor() as f32 * self.scale_factor;
+        // Samples were taken while the previous view was shown, so they are mapped
+        // before the camera moves.
+        let grid = self.render_pass_manager.texel_grid();
+        let samples: Vec<Vec2> = self
+            .scene_input
+            .pointer_samples
+            .drain(..)
+            .map(|sample| self.camera.screen_to_texel(sample, grid))
+            .collect();
+        self.tools.set_seed(self.render_pass_manager.seed());
+        let actions = self.tools.update(self.scene_input.paint, &samples);
+        self.scene_input.update_camera(&mut self.camera);
+        self.render_pass_manager.follow_camera(&self.camera);
+        for action in actions {
+            match action {
+                ToolAction::Stamp(stamp) => self.render_pass_manager.paint(&self.layers, stamp),
+                ToolAction::FloodFill {
+                    seed,
+                    color,
+                    tolerance,
+                } => self
+                    .render_pass_manager
+                    .flood_fill(&self.layers, seed, color, tolerance),
+                ToolAction::Commit(label) => self.history.commit(label),
+            }
+        }
+
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [width, height],
             pixels_per_point: self.window.scale_factor() as f32 * self.scale_factor,
@@ -244,17 +300,45 @@ impl AppState {
                 .device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-            self.render_pass_manager
-                .render(&surface_view, &mut encoder, &self.device);
+            let time = self.egui_renderer.context().input(|input| input.time);
+            let seed = self.render_pass_manager.seed();
+            let lights: Vec<Light> = self
+                .render_pass_manager
+                .lights()
+                .iter()
+                .map(|light| light.animated(time, seed))
+                .collect();
+            self.render_pass_manager.render(
+                &surface_view,
+                &mut encoder,
+                &self.device,
+                &self.queue,
+                &mut self.history,
+                &mut self.layers,
+                &self.objects,
+                &self.lit_sprites,
+                &lights,
+                &self.camera,
+            );
 
             self.egui_renderer.begin_frame(&self.window);
             let vsync_enabled = self.vsync_enabled;
+            self.reference.samples = self.render_pass_manager.reference_samples();
+            self.reference.has_reference = self.render_pass_manager.has_reference();
+            let (render_options, lights) = self.render_pass_manager.options_and_lights();
             self.engine_gui.render_gui(
-                &mut self.color,
-                &mut self.paint,
-                &mut self.mouse_pos,
-                &mut self.brush_radius,
-                self.render_pass_manager.get_options(),
+                &mut self.tools,
+                &mut self.history,
+                &mut self.layers,
+                &mut self.objects,
+                &mut self.lit_sprites,
+                lights,
+                &mut self.canvas,
+                &self.camera,
+                grid,
+                &mut self.scene_input,
+                &mut self.reference,
+                render_options,
                 &mut self.vsync_enabled,
             );
             if vsync_enabled != self.vsync_enabled {
@@ -271,7 +355,9 @@ impl AppState {
             );
 
             self.queue.submit(Some(encoder.finish()));
+            self.render_pass_manager.submitted();
         }
+        self.apply_reference_requests();
 
         surface_texture.present();
 
@@ -281,7 +367,54 @@ impl AppState {
         self.window.request_redraw();
     }
 
+    /// Requests radiance readbacks for the next frame and applies the finished ones.
+    fn apply_reference_requests(&mut self) {
+        if std::mem::take(&mut self.reference.save) {
+            self.render_pass_manager
+                .read_radiance(RadianceReadback::Save);
+        }
+        if std::mem::take(&mut self.reference.compare) {
+            self.render_pass_manager
+                .read_radiance(RadianceReadback::Compare);
+        }
+        match self
+            .render_pass_manager
+            .take_radiance_readback(&self.device)
+        {
+            Some(ReferenceReadback::Saved {
+                width,
+                height,
+                pixels,
+            }) => write_reference(width, height, &pixels),
+            Some(ReferenceReadback::Compared(error)) => self.reference.error = error,
+            None => {}
+        }
+    }
+
     pub fn event(&mut self, event: &WindowEvent) {
         self.egui_renderer.handle_input(&self.window, event);
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_reference(width: u32, height: u32, pixels: &[[f32; 4]]) {
+    use crate::render_passes::radiance_cascades_passes::reference::write_pfm;
+    use std::io::Write;
+
+    let path = std::path::Path::new(REFERENCE_PATH);
+    let result = std::fs::File::create(path).and_then(|file| {
+        let mut file = std::io::BufWriter::new(file);
+        write_pfm(&mut file, width, height, pixels)?;
+        file.flush()
+    });
+    match result {
+        Ok(()) => info!("Saved the reference to {}", path.display()),
+        Err(err) => log::error!("Failed to save the reference: {err}"),
+    }
+}
+
+/// There is no file to write to on the web, the reference is only kept to compare to.
+#[cfg(target_arch = "wasm32")]
+fn write_reference(_width: u32, _height: u32, _pixels: &[[f32; 4]]) {
+    info!("Kept the reference to compare to");
+}
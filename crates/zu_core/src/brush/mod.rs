@@ -0,0 +1,46 @@
+pub mod stroke;
+pub mod tools;
+
+use glam::Vec2;
+
+use crate::render_passes::brush_pass::BrushStamp;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub color: [f32; 4],
+    pub radius: u32,
+    /// Distance between stamps along a stroke, as a fraction of the radius.
+    pub spacing: f32,
+    pub hardness: f32,
+    pub opacity: f32,
+    /// Interpolate pointer samples with a Catmull-Rom spline instead of straight lines.
+    pub smoothing: bool,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0, 1.0],
+            radius: 10,
+            spacing: 0.25,
+            hardness: 1.0,
+            opacity: 1.0,
+            smoothing: true,
+        }
+    }
+}
+
+impl Brush {
+    /// Stamp spacing in texels, never below half a texel.
+    pub fn spacing_px(&self) -> f32 {
+        (self.radius as f32 * self.spacing).max(0.5)
+    }
+
+    pub fn stamp(&self, center: Vec2) -> BrushStamp {
+        BrushStamp {
+            hardness: self.hardness,
+            opacity: self.opacity,
+            ..BrushStamp::circle(center, self.radius as f32, self.color)
+        }
+    }
+}
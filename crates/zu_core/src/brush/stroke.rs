@@ -0,0 +1,103 @@
+use glam::Vec2;
+
+/// Turns pointer samples into evenly spaced stamp positions.
+///
+/// Samples may arrive in bursts between frames, so the distance walked since the last
+/// stamp is carried over from one call to the next.
+#[derive(Debug, Default)]
+pub struct Stroke {
+    /// Last samples, used as Catmull-Rom control points.
+    samples: Vec<Vec2>,
+    /// End of the polyline walked so far.
+    cursor: Option<Vec2>,
+    until_next_stamp: f32,
+}
+
+impl Stroke {
+    pub fn is_active(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Adds pointer samples and returns the stamp positions they produced.
+    pub fn add_samples(&mut self, samples: &[Vec2], spacing: f32, smoothing: bool) -> Vec<Vec2> {
+        let mut stamps = Vec::new();
+        for &sample in samples {
+            if self.samples.last() == Some(&sample) {
+                continue;
+            }
+            self.samples.push(sample);
+            if !smoothing {
+                self.walk_to(sample, spacing, &mut stamps);
+            } else if self.samples.len() == 1 {
+                // The first sample is stamped right away, segments lag one sample behind.
+                self.walk_to(sample, spacing, &mut stamps);
+            } else if self.samples.len() >= 3 {
+                let n = self.samples.len();
+                let p0 = self.samples[n.saturating_sub(4).min(n - 3)];
+                let [p1, p2, p3] = [self.samples[n - 3], self.samples[n - 2], sample];
+                self.walk_spline(p0, p1, p2, p3, spacing, &mut stamps);
+            }
+            if self.samples.len() > 4 {
+                self.samples.remove(0);
+            }
+        }
+        stamps
+    }
+
+    /// Ends the stroke, returning stamps for the segment still held back by smoothing.
+    pub fn finish(&mut self, spacing: f32, smoothing: bool) -> Vec<Vec2> {
+        let mut stamps = Vec::new();
+        let n = self.samples.len();
+        if smoothing && n >= 2 {
+            let p0 = self.samples[n.saturating_sub(3)];
+            let [p1, p2] = [self.samples[n - 2], self.samples[n - 1]];
+            self.walk_spline(p0, p1, p2, p2, spacing, &mut stamps);
+        }
+        *self = Self::default();
+        stamps
+    }
+
+    fn walk_spline(
+        &mut self,
+        p0: Vec2,
+        p1: Vec2,
+        p2: Vec2,
+        p3: Vec2,
+        spacing: f32,
+        stamps: &mut Vec<Vec2>,
+    ) {
+        let pieces = ((p2 - p1).length() / spacing).ceil().clamp(1.0, 64.0) as u32;
+        for i in 1..=pieces {
+            let point = catmull_rom(p0, p1, p2, p3, i as f32 / pieces as f32);
+            self.walk_to(point, spacing, stamps);
+        }
+    }
+
+    fn walk_to(&mut self, to: Vec2, spacing: f32, stamps: &mut Vec<Vec2>) {
+        let Some(from) = self.cursor else {
+            stamps.push(to);
+            self.cursor = Some(to);
+            self.until_next_stamp = spacing;
+            return;
+        };
+        let segment = to - from;
+        let length = segment.length();
+        let mut travelled = 0.0;
+        while travelled + self.until_next_stamp <= length {
+            travelled += self.until_next_stamp;
+            stamps.push(from + segment * (travelled / length));
+            self.until_next_stamp = spacing;
+        }
+        self.until_next_stamp -= length - travelled;
+        self.cursor = Some(to);
+    }
+}
+
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
@@ -0,0 +1,317 @@
+use glam::Vec2;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    brush::{Brush, stroke::Stroke},
+    render_passes::brush_pass::{BrushStamp, StampMode, StampShape},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Brush,
+    Eraser,
+    Line,
+    Rectangle,
+    Ellipse,
+    FloodFill,
+    Spray,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 7] = [
+        Tool::Brush,
+        Tool::Eraser,
+        Tool::Line,
+        Tool::Rectangle,
+        Tool::Ellipse,
+        Tool::FloodFill,
+        Tool::Spray,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Eraser => "Eraser",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::FloodFill => "Fill",
+            Tool::Spray => "Spray",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineOptions {
+    pub width: f32,
+    pub hardness: f32,
+}
+
+impl Default for LineOptions {
+    fn default() -> Self {
+        Self {
+            width: 8.0,
+            hardness: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeOptions {
+    pub filled: bool,
+    pub outline_width: f32,
+}
+
+impl Default for ShapeOptions {
+    fn default() -> Self {
+        Self {
+            filled: true,
+            outline_width: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloodFillOptions {
+    /// Largest per channel difference to the clicked texel that is still filled.
+    pub tolerance: f32,
+}
+
+impl Default for FloodFillOptions {
+    fn default() -> Self {
+        Self { tolerance: 0.05 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SprayOptions {
+    pub radius: f32,
+    pub dot_radius: f32,
+    /// Dots per frame while the pointer is held down.
+    pub density: u32,
+    pub opacity: f32,
+}
+
+impl Default for SprayOptions {
+    fn default() -> Self {
+        Self {
+            radius: 40.0,
+            dot_radius: 1.5,
+            density: 20,
+            opacity: 1.0,
+        }
+    }
+}
+
+pub enum ToolAction {
+    Stamp(BrushStamp),
+    /// Runs over a few frames on the GPU and is added to the history once done.
+    FloodFill {
+        seed: Vec2,
+        color: [f32; 4],
+        tolerance: f32,
+    },
+    /// The edit made with the named tool is complete and can be added to the history.
+    Commit(&'static str),
+}
+
+/// Active tool with the options of every tool. The color of `brush` is shared by all tools.
+#[derive(Debug)]
+pub struct ToolPalette {
+    pub tool: Tool,
+    pub brush: Brush,
+    pub eraser: Brush,
+    pub line: LineOptions,
+    pub rectangle: ShapeOptions,
+    pub ellipse: ShapeOptions,
+    pub flood_fill: FloodFillOptions,
+    pub spray: SprayOptions,
+    stroke: Stroke,
+    /// Start and current position of a line, rectangle or ellipse being dragged.
+    drag: Option<(Vec2, Vec2)>,
+    position: Option<Vec2>,
+    was_down: bool,
+    /// Places the spray dots, follows the engine seed.
+    rng: StdRng,
+    seed: u32,
+}
+
+impl Default for ToolPalette {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Brush,
+            brush: Default::default(),
+            eraser: Brush {
+                radius: 20,
+                ..Default::default()
+            },
+            line: Default::default(),
+            rectangle: Default::default(),
+            ellipse: Default::default(),
+            flood_fill: Default::default(),
+            spray: Default::default(),
+            stroke: Default::default(),
+            drag: None,
+            position: None,
+            was_down: false,
+            rng: StdRng::seed_from_u64(0),
+            seed: 0,
+        }
+    }
+}
+
+impl ToolPalette {
+    /// Restarts the random sequence of the tools from `seed`, if it changed.
+    pub fn set_seed(&mut self, seed: u32) {
+        if seed != self.seed {
+            self.seed = seed;
+            self.rng = StdRng::seed_from_u64(seed as u64);
+        }
+    }
+
+    /// Feeds the pointer state of one frame to the active tool.
+    pub fn update(&mut self, down: bool, samples: &[Vec2]) -> Vec<ToolAction> {
+        let pressed = down && !self.was_down;
+        let released = !down && self.was_down;
+        self.was_down = down;
+        if let Some(&last) = samples.last() {
+            self.position = Some(last);
+        }
+
+        let mut actions = Vec::new();
+        match self.tool {
+            Tool::Brush | Tool::Eraser => {
+                let (brush, mode) = match self.tool {
+                    Tool::Eraser => (self.eraser, StampMode::Erase),
+                    _ => (self.brush, StampMode::Mix),
+                };
+                let spacing = brush.spacing_px();
+                let finished = !down && self.stroke.is_active();
+                let centers = if down {
+                    self.stroke.add_samples(samples, spacing, brush.smoothing)
+                } else if finished {
+                    // The release frame still brings the samples up to the release.
+                    let mut centers = self.stroke.add_samples(samples, spacing, brush.smoothing);
+                    centers.extend(self.stroke.finish(spacing, brush.smoothing));
+                    centers
+                } else {
+                    Vec::new()
+                };
+                actions.extend(
+                    centers
+                        .into_iter()
+                        .map(|center| ToolAction::Stamp(brush.stamp(center).with_mode(mode))),
+                );
+                if finished {
+                    actions.push(ToolAction::Commit(self.tool.name()));
+                }
+            }
+            Tool::Line | Tool::Rectangle | Tool::Ellipse => {
+                if let Some(position) = self.position
+                    && (down || released)
+                {
+                    let start = self.drag.map_or(position, |(start, _)| start);
+                    self.drag = Some((start, position));
+                }
+                if !down && let Some(stamp) = self.preview() {
+                    actions.push(ToolAction::Stamp(stamp));
+                    actions.push(ToolAction::Commit(self.tool.name()));
+                    self.drag = None;
+                }
+            }
+            Tool::FloodFill => {
+                if let (true, Some(seed)) = (pressed, self.position) {
+                    actions.push(ToolAction::FloodFill {
+                        seed,
+                        color: self.brush.color,
+                        tolerance: self.flood_fill.tolerance,
+                    });
+                }
+            }
+            Tool::Spray => {
+                if let (true, Some(center)) = (down, self.position) {
+                    for _ in 0..self.spray.density {
+                        // sqrt keeps the dots uniformly distributed over the disk.
+                        let distance = self.spray.radius * self.rng.random::<f32>().sqrt();
+                        let angle = self.rng.random::<f32>() * std::f32::consts::TAU;
+                        let offset = Vec2::from_angle(angle) * distance;
+                        actions.push(ToolAction::Stamp(BrushStamp {
+                            opacity: self.spray.opacity,
+                            ..BrushStamp::circle(
+                                center + offset,
+                                self.spray.dot_radius,
+                                self.brush.color,
+                            )
+                        }));
+                    }
+                }
+                if released {
+                    actions.push(ToolAction::Commit(self.tool.name()));
+                }
+            }
+        }
+        if !matches!(self.tool, Tool::Brush | Tool::Eraser) && self.stroke.is_active() {
+            self.stroke = Default::default();
+        }
+        if !matches!(self.tool, Tool::Line | Tool::Rectangle | Tool::Ellipse) {
+            self.drag = None;
+        }
+        actions
+    }
+
+    /// Shape that is committed when the current drag is released.
+    pub fn preview(&self) -> Option<BrushStamp> {
+        let (start, end) = self.drag?;
+        let color = self.brush.color;
+        let shape_stamp = |shape, options: &ShapeOptions| BrushStamp {
+            outline: if options.filled {
+                0.0
+            } else {
+                options.outline_width
+            },
+            ..BrushStamp::shape_between(shape, start, end, color)
+        };
+        match self.tool {
+            Tool::Line => Some(BrushStamp {
+                hardness: self.line.hardness,
+                ..BrushStamp::line(start, end, self.line.width * 0.5, color)
+            }),
+            Tool::Rectangle => Some(shape_stamp(StampShape::Rect, &self.rectangle)),
+            Tool::Ellipse => Some(shape_stamp(StampShape::Ellipse, &self.ellipse)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dots of one spray frame after setting `seeds` in order.
+    fn spray(seeds: &[u32]) -> Vec<[f32; 2]> {
+        let mut tools = ToolPalette {
+            tool: Tool::Spray,
+            ..Default::default()
+        };
+        for &seed in seeds {
+            tools.set_seed(seed);
+        }
+        tools
+            .update(true, &[Vec2::new(100.0, 100.0)])
+            .into_iter()
+            .filter_map(|action| match action {
+                ToolAction::Stamp(stamp) => Some(stamp.center),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spray_follows_the_seed() {
+        assert!(!spray(&[1]).is_empty());
+        assert_eq!(spray(&[1]), spray(&[1]));
+        assert_ne!(spray(&[1]), spray(&[2]));
+        // Going back to a seed restarts its sequence.
+        assert_eq!(spray(&[1, 2, 1]), spray(&[1]));
+    }
+}
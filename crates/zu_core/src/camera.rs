@@ -1,6 +1,8 @@
 use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Vec2};
 
+use crate::world::TexelGrid;
+
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct Camera {
     pub left: f32,
@@ -10,8 +12,13 @@ pub struct Camera {
     pub near: f32,
     pub far: f32,
     pub zoom_factor: f32,
+    /// Center of the view in world units, y up.
     pub position: Vec2,
     pub aspect_ratio: f32,
+    /// Viewport size in physical pixels.
+    pub screen_size: Vec2,
+    /// Physical pixels per logical screen point.
+    pub pixels_per_point: f32,
 }
 
 #[repr(C)]
@@ -34,14 +41,23 @@ impl Default for Camera {
             zoom_factor: 1.0,
             position: Vec2::ZERO,
             aspect_ratio: 1.0,
+            screen_size: Vec2::ONE,
+            pixels_per_point: 1.0,
         }
     }
 }
 
 impl Camera {
+    pub const MIN_ZOOM: f32 = 0.1;
+    pub const MAX_ZOOM: f32 = 1000.0;
+
     #[inline]
     fn clamp_zoom(zoom: f32) -> f32 {
-        zoom.max(0.1)
+        if zoom.is_nan() {
+            Self::MIN_ZOOM
+        } else {
+            zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM)
+        }
     }
 
     pub fn new(
@@ -65,6 +81,8 @@ impl Camera {
             zoom_factor,
             position,
             aspect_ratio: (right - left) / (top - bottom),
+            screen_size: Vec2::new(right - left, top - bottom),
+            pixels_per_point: 1.0,
         }
     }
 
@@ -88,10 +106,13 @@ impl Camera {
             zoom_factor,
             position,
             aspect_ratio: aspect,
+            screen_size: Vec2::new(width, height),
+            pixels_per_point: 1.0,
         }
     }
 
     pub fn update_from_screen_size(&mut self, width: f32, height: f32) {
+        self.screen_size = Vec2::new(width, height);
         self.aspect_ratio = width / height;
         self.left = -self.aspect_ratio;
         self.right = self.aspect_ratio;
@@ -108,7 +129,7 @@ impl Camera {
             self.top / zoom,
             self.near,
             self.far,
-        ) * Mat4::from_translation(Vec2::new(-self.position.x, self.position.y).extend(0.0))
+        ) * Mat4::from_translation((-self.position).extend(0.0))
     }
 
     pub fn get_camera_uninform(&self) -> CameraUniform {
@@ -132,4 +153,172 @@ impl Camera {
         };
         self.set_zoom(new_zoom);
     }
+
+    /// World units covered by one physical pixel.
+    pub fn world_per_pixel(&self) -> f32 {
+        (self.top - self.bottom) / (self.zoom_factor * self.screen_size.y)
+    }
+
+    /// World position of the top left corner of the viewport.
+    pub fn view_top_left(&self) -> Vec2 {
+        self.position + Vec2::new(self.left, self.top) / self.zoom_factor
+    }
+
+    /// Physical pixel, y down from the top left corner, to world position.
+    pub fn pixel_to_world(&self, pixel: Vec2) -> Vec2 {
+        self.view_top_left() + Vec2::new(pixel.x, -pixel.y) * self.world_per_pixel()
+    }
+
+    pub fn world_to_pixel(&self, world: Vec2) -> Vec2 {
+        let pixel = (world - self.view_top_left()) / self.world_per_pixel();
+        Vec2::new(pixel.x, -pixel.y)
+    }
+
+    /// Logical screen point, as reported by egui and winit, to world position.
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        self.pixel_to_world(screen * self.pixels_per_point)
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        self.world_to_pixel(world) / self.pixels_per_point
+    }
+
+    pub fn screen_to_texel(&self, screen: Vec2, grid: TexelGrid) -> Vec2 {
+        grid.world_to_texel(self.screen_to_world(screen))
+    }
+
+    pub fn texel_to_screen(&self, texel: Vec2, grid: TexelGrid) -> Vec2 {
+        self.world_to_screen(grid.texel_to_world(texel))
+    }
+
+    /// Moves the view so the world follows a drag of `delta` screen points.
+    pub fn pan(&mut self, delta: Vec2) {
+        self.position -=
+            Vec2::new(delta.x, -delta.y) * self.pixels_per_point * self.world_per_pixel();
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the world point under `screen` in place.
+    pub fn zoom_at(&mut self, factor: f32, screen: Vec2) {
+        let anchor = self.screen_to_world(screen);
+        self.set_zoom(self.zoom_factor * factor);
+        self.position += anchor - self.screen_to_world(screen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cameras() -> Vec<Camera> {
+        let mut cameras = Vec::new();
+        for (width, height) in [(1920.0, 1080.0), (800.0, 1200.0), (1.0, 1.0)] {
+            for zoom in [0.1, 1.0, 3.7] {
+                for position in [Vec2::ZERO, Vec2::new(-2.5, 14.0)] {
+                    for pixels_per_point in [1.0, 1.5, 2.0] {
+                        let mut camera =
+                            Camera::from_screen_size(width, height, 0.1, 1000.0, zoom, position);
+                        camera.pixels_per_point = pixels_per_point;
+                        cameras.push(camera);
+                    }
+                }
+            }
+        }
+        cameras
+    }
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(
+            a.abs_diff_eq(b, 1e-3 * (1.0 + b.abs().max_element())),
+            "{a} != {b}"
+        );
+    }
+
+    #[test]
+    fn screen_world_round_trip() {
+        for camera in cameras() {
+            for screen in [Vec2::ZERO, Vec2::new(10.0, 20.0), Vec2::new(640.0, -35.5)] {
+                assert_close(
+                    camera.world_to_screen(camera.screen_to_world(screen)),
+                    screen,
+                );
+            }
+            for world in [Vec2::ZERO, Vec2::new(1.0, -1.0), Vec2::new(-7.25, 3.0)] {
+                assert_close(camera.screen_to_world(camera.world_to_screen(world)), world);
+            }
+        }
+    }
+
+    #[test]
+    fn screen_center_is_camera_position() {
+        for camera in cameras() {
+            let center = camera.screen_size / camera.pixels_per_point * 0.5;
+            assert_close(camera.screen_to_world(center), camera.position);
+        }
+    }
+
+    #[test]
+    fn texel_round_trip() {
+        let grid = TexelGrid {
+            texels_per_unit: 540.0,
+        };
+        for camera in cameras() {
+            for texel in [
+                Vec2::ZERO,
+                Vec2::new(960.0, -540.0),
+                Vec2::new(-1919.5, 3.0),
+            ] {
+                let screen = camera.texel_to_screen(texel, grid);
+                // Far from the camera a texel is a small fraction of the world position.
+                let round_trip = camera.screen_to_texel(screen, grid);
+                assert!(
+                    round_trip.abs_diff_eq(texel, 1e-2),
+                    "{round_trip} != {texel}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn texels_match_pixels_at_zoom_one() {
+        let camera = Camera::from_screen_size(1920.0, 1080.0, 0.1, 1000.0, 1.0, Vec2::ZERO);
+        let grid = TexelGrid {
+            texels_per_unit: 540.0,
+        };
+        assert_close(
+            camera.screen_to_texel(Vec2::ZERO, grid),
+            Vec2::new(-960.0, -540.0),
+        );
+        assert_close(
+            camera.screen_to_texel(Vec2::new(1920.0, 1080.0), grid),
+            Vec2::new(960.0, 540.0),
+        );
+    }
+
+    #[test]
+    fn zoom_at_keeps_anchor_in_place() {
+        for mut camera in cameras() {
+            let screen = Vec2::new(100.0, 250.0);
+            let before = camera.screen_to_world(screen);
+            camera.zoom_at(1.25, screen);
+            assert_close(camera.screen_to_world(screen), before);
+        }
+    }
+
+    #[test]
+    fn clamp_zoom_edge_cases() {
+        assert_eq!(Camera::clamp_zoom(1.0), 1.0);
+        assert_eq!(Camera::clamp_zoom(Camera::MIN_ZOOM), Camera::MIN_ZOOM);
+        assert_eq!(Camera::clamp_zoom(0.0), Camera::MIN_ZOOM);
+        assert_eq!(Camera::clamp_zoom(-5.0), Camera::MIN_ZOOM);
+        assert_eq!(Camera::clamp_zoom(f32::NEG_INFINITY), Camera::MIN_ZOOM);
+        assert_eq!(Camera::clamp_zoom(f32::NAN), Camera::MIN_ZOOM);
+        assert_eq!(Camera::clamp_zoom(Camera::MAX_ZOOM), Camera::MAX_ZOOM);
+        assert_eq!(Camera::clamp_zoom(f32::INFINITY), Camera::MAX_ZOOM);
+
+        let mut camera = Camera::default();
+        camera.set_zoom(0.0);
+        assert_eq!(camera.zoom_factor, Camera::MIN_ZOOM);
+        let camera = Camera::from_screen_size(100.0, 100.0, 0.1, 1000.0, -1.0, Vec2::ZERO);
+        assert_eq!(camera.zoom_factor, Camera::MIN_ZOOM);
+    }
 }
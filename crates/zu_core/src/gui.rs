@@ -1,14 +1,89 @@
-use crate::render_passes::render_pass_manager::RenderOptions;
+use crate::brush::{
+    Brush,
+    tools::{ShapeOptions, Tool, ToolPalette},
+};
+use crate::camera::Camera;
+use crate::history::History;
+use crate::layers::{BlendMode, FULL_HEIGHT, LayerStack};
+use crate::lights::{Light, LightAnimation, LightKind};
+use crate::render_passes::brush_pass::{BrushStamp, StampShape};
+use crate::render_passes::lit_sprite_pass::{LitSprite, LitSpriteId};
+use crate::render_passes::object_render_pass::{ObjectShape, SceneObject};
+use crate::render_passes::radiance_cascades_passes::reference::ImageError;
+use crate::render_passes::render_pass_manager::{CanvasAnchor, CanvasSettings, RenderOptions};
 use crate::widgets::usage_diagnostics::UsageDiagnostics;
+use crate::world::TexelGrid;
 use egui::Context;
 use egui::Widget;
 use egui_probe::Probe;
 use glam::Vec2;
 use puffin_egui::profiler_window;
 
+/// Scene input collected by the GUI during a frame, for the input egui did not take.
+#[derive(Debug)]
+pub struct SceneInput {
+    pub paint: bool,
+    /// Pointer positions in logical screen points.
+    pub pointer_samples: Vec<Vec2>,
+    /// Screen points the view was dragged by.
+    pub pan: Vec2,
+    pub zoom: f32,
+    pub zoom_center: Vec2,
+    pub reset_view: bool,
+}
+
+impl Default for SceneInput {
+    fn default() -> Self {
+        Self {
+            paint: false,
+            pointer_samples: Vec::new(),
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+            zoom_center: Vec2::ZERO,
+            reset_view: false,
+        }
+    }
+}
+
+impl SceneInput {
+    /// Applies and resets the navigation collected so far.
+    pub fn update_camera(&mut self, camera: &mut Camera) {
+        if std::mem::take(&mut self.reset_view) {
+            camera.position = Vec2::ZERO;
+            camera.set_zoom(1.0);
+        }
+        if self.pan != Vec2::ZERO {
+            camera.pan(std::mem::take(&mut self.pan));
+        }
+        if self.zoom != 1.0 {
+            camera.zoom_at(std::mem::replace(&mut self.zoom, 1.0), self.zoom_center);
+        }
+    }
+}
+
+/// Reference mode status for the GUI, and the requests it makes, applied once the frame
+/// is submitted.
+#[derive(Debug, Default)]
+pub struct ReferenceControls {
+    /// Frames averaged so far, `None` outside the reference mode.
+    pub samples: Option<u32>,
+    pub has_reference: bool,
+    /// Result of the last comparison.
+    pub error: Option<ImageError>,
+    pub save: bool,
+    pub compare: bool,
+}
+
 pub struct EngineGui {
     egui_context: Context,
     open_profiler_window: bool,
+    /// Canvas settings being edited, applied with the "Resize canvas" button.
+    canvas_edit: Option<CanvasSettings>,
+    /// The current press started over the scene, so it keeps painting even when the
+    /// pointer moves over a window.
+    scene_has_pointer: bool,
+    /// Between the press and the release of a stroke, pointer positions are samples.
+    stroking: bool,
 }
 
 impl EngineGui {
@@ -16,39 +91,569 @@ impl EngineGui {
         Self {
             egui_context: context.clone(),
             open_profiler_window: false,
+            canvas_edit: None,
+            scene_has_pointer: false,
+            stroking: false,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn render_gui(
         &mut self,
-        color: &mut [f32; 4],
-        paint: &mut bool,
-        pointer_pos: &mut Vec2,
-        brush_radius: &mut u32,
+        tools: &mut ToolPalette,
+        history: &mut History,
+        layers: &mut LayerStack,
+        objects: &mut Vec<SceneObject>,
+        lit_sprites: &mut Vec<LitSprite>,
+        lights: &mut Vec<Light>,
+        canvas: &mut CanvasSettings,
+        camera: &Camera,
+        texel_grid: TexelGrid,
+        scene_input: &mut SceneInput,
+        reference: &mut ReferenceControls,
         render_options: &mut RenderOptions,
         vsync_enabled: &mut bool,
     ) {
-        *pointer_pos = {
-            if let Some(pos) = self.egui_context.pointer_latest_pos() {
-                let (x, y) = (pos.x, pos.y);
-                Vec2 { x, y } * self.egui_context.pixels_per_point()
-            } else {
-                Vec2::ZERO
-            }
-        };
+        // Decided from the previous frame's layout, the windows of this one are not built yet.
+        let gui_wants_pointer = self.egui_context.wants_pointer_input();
+        let gui_wants_keyboard = self.egui_context.wants_keyboard_input();
         self.egui_context.input(|input| {
-            *paint = input.pointer.primary_down();
+            if input.pointer.primary_pressed() {
+                self.scene_has_pointer = !gui_wants_pointer;
+            }
+            let multi_touch = input.multi_touch();
+            // Two fingers navigate instead of painting.
+            scene_input.paint =
+                input.pointer.primary_down() && self.scene_has_pointer && multi_touch.is_none();
+            // Every pointer position of the stroke since the last frame, so fast strokes
+            // have no gaps. Moves before the press are not part of it, the release is.
+            for event in &input.events {
+                match event {
+                    egui::Event::PointerButton {
+                        pos,
+                        button: egui::PointerButton::Primary,
+                        pressed,
+                        ..
+                    } => {
+                        if *pressed {
+                            self.stroking = self.scene_has_pointer && multi_touch.is_none();
+                        }
+                        if self.stroking {
+                            scene_input.pointer_samples.push(Vec2::new(pos.x, pos.y));
+                        }
+                        if !*pressed {
+                            self.stroking = false;
+                        }
+                    }
+                    egui::Event::PointerMoved(pos) if self.stroking => {
+                        scene_input.pointer_samples.push(Vec2::new(pos.x, pos.y));
+                    }
+                    _ => {}
+                }
+            }
+            if gui_wants_pointer && !scene_input.paint {
+                return;
+            }
+
+            // Pinch and ctrl + wheel arrive as zoom_delta, a plain wheel as scrolling.
+            let zoom = input.zoom_delta() * (input.smooth_scroll_delta.y * 0.002).exp();
+            if zoom != 1.0 {
+                let center = multi_touch
+                    .map(|touch| touch.center_pos)
+                    .or(input.pointer.hover_pos());
+                if let Some(center) = center {
+                    scene_input.zoom *= zoom;
+                    scene_input.zoom_center = Vec2::new(center.x, center.y);
+                }
+            }
+            if input.pointer.middle_down() {
+                let delta = input.pointer.delta();
+                scene_input.pan += Vec2::new(delta.x, delta.y);
+            }
+            if let Some(touch) = multi_touch {
+                let delta = touch.translation_delta;
+                scene_input.pan += Vec2::new(delta.x, delta.y);
+            }
+        });
+        self.egui_context.input_mut(|input| {
+            if gui_wants_keyboard {
+                return;
+            }
+            let redo = egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::Z,
+            );
+            let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+            // Ctrl+Z also matches with shift held, so redo has to be checked first.
+            if input.consume_shortcut(&redo) {
+                history.redo();
+            } else if input.consume_shortcut(&undo) {
+                history.undo();
+            }
         });
         egui::Window::new("Engine Window").show(&self.egui_context, |ui| {
-            ui.color_edit_button_rgba_unmultiplied(color);
-            ui.add(egui::Slider::new(brush_radius, 0..=120).text("brush radius"));
+            tools_ui(ui, tools);
+            let canvas_edit = self.canvas_edit.get_or_insert(*canvas);
+            canvas_ui(ui, canvas_edit, canvas);
+            if ui.button("Reset view").clicked() {
+                scene_input.reset_view = true;
+            }
             Probe::new(render_options).show(ui);
+            reference_ui(ui, reference);
             UsageDiagnostics {}.ui(ui);
             ui.checkbox(vsync_enabled, "Vsync enabled");
             ui.checkbox(&mut self.open_profiler_window, "Open profiler window")
         });
+        egui::Window::new("Layers").show(&self.egui_context, |ui| layers_ui(ui, layers));
+        egui::Window::new("Objects")
+            .default_open(false)
+            .show(&self.egui_context, |ui| objects_ui(ui, objects, camera));
+        egui::Window::new("Lit sprites")
+            .default_open(false)
+            .show(&self.egui_context, |ui| {
+                lit_sprites_ui(ui, lit_sprites, camera)
+            });
+        egui::Window::new("Lights")
+            .default_open(false)
+            .show(&self.egui_context, |ui| lights_ui(ui, lights, camera));
+        egui::Window::new("History")
+            .default_open(false)
+            .show(&self.egui_context, |ui| history_ui(ui, history));
+        if let Some(stamp) = tools.preview() {
+            self.draw_shape_preview(&stamp, camera, texel_grid);
+        }
         if self.open_profiler_window {
             profiler_window(&self.egui_context);
         }
     }
+
+    /// Outlines the shape being dragged, it is only painted on release.
+    fn draw_shape_preview(&self, stamp: &BrushStamp, camera: &Camera, grid: TexelGrid) {
+        let to_pos = |p: [f32; 2]| {
+            let p = camera.texel_to_screen(Vec2::from(p), grid);
+            egui::pos2(p.x, p.y)
+        };
+        let painter = self.egui_context.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("shape_preview"),
+        ));
+        let stroke = egui::Stroke::new(1.0, egui::Color32::WHITE);
+        let center = to_pos(stamp.center);
+        let extent = (to_pos([
+            stamp.center[0] + stamp.extent[0],
+            stamp.center[1] + stamp.extent[1],
+        ]) - center)
+            .abs();
+        if stamp.shape == StampShape::Line as u32 {
+            painter.line_segment([center, to_pos(stamp.extent)], stroke);
+        } else if stamp.shape == StampShape::Rect as u32 {
+            painter.rect_stroke(
+                egui::Rect::from_center_size(center, extent * 2.0),
+                0.0,
+                stroke,
+                egui::StrokeKind::Middle,
+            );
+        } else if stamp.shape == StampShape::Ellipse as u32 {
+            painter.add(egui::Shape::ellipse_stroke(center, extent, stroke));
+        }
+    }
+}
+
+fn canvas_ui(ui: &mut egui::Ui, edit: &mut CanvasSettings, canvas: &mut CanvasSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Canvas");
+        ui.add(egui::DragValue::new(&mut edit.width).range(1..=8192));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut edit.height).range(1..=8192));
+        egui::ComboBox::from_id_salt("canvas_anchor")
+            .selected_text(format!("{:?}", edit.anchor))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut edit.anchor, CanvasAnchor::TopLeft, "TopLeft");
+                ui.selectable_value(&mut edit.anchor, CanvasAnchor::Center, "Center");
+            });
+        if ui
+            .add_enabled(edit != canvas, egui::Button::new("Resize canvas"))
+            .clicked()
+        {
+            *canvas = *edit;
+        }
+    });
+}
+
+fn reference_ui(ui: &mut egui::Ui, reference: &mut ReferenceControls) {
+    ui.horizontal(|ui| {
+        if let Some(samples) = reference.samples {
+            ui.label(format!("Reference: {samples} frames"));
+            if ui.button("Save reference").clicked() {
+                reference.save = true;
+            }
+        }
+        if reference.has_reference && ui.button("Compare to reference").clicked() {
+            reference.compare = true;
+        }
+    });
+    if let Some(error) = reference.error {
+        ui.label(format!("RMSE {:.5}, PSNR {:.2} dB", error.rmse, error.psnr));
+    }
+}
+
+fn tools_ui(ui: &mut egui::Ui, tools: &mut ToolPalette) {
+    ui.horizontal_wrapped(|ui| {
+        for tool in Tool::ALL {
+            ui.selectable_value(&mut tools.tool, tool, tool.name());
+        }
+    });
+    if tools.tool != Tool::Eraser {
+        ui.color_edit_button_rgba_unmultiplied(&mut tools.brush.color);
+    }
+    match tools.tool {
+        Tool::Brush => brush_ui(ui, &mut tools.brush),
+        Tool::Eraser => brush_ui(ui, &mut tools.eraser),
+        Tool::Line => {
+            ui.add(egui::Slider::new(&mut tools.line.width, 1.0..=256.0).text("line width"));
+            ui.add(egui::Slider::new(&mut tools.line.hardness, 0.0..=1.0).text("line hardness"));
+        }
+        Tool::Rectangle => shape_ui(ui, &mut tools.rectangle),
+        Tool::Ellipse => shape_ui(ui, &mut tools.ellipse),
+        Tool::FloodFill => {
+            ui.add(
+                egui::Slider::new(&mut tools.flood_fill.tolerance, 0.0..=1.0)
+                    .text("fill tolerance"),
+            );
+        }
+        Tool::Spray => {
+            let spray = &mut tools.spray;
+            ui.add(egui::Slider::new(&mut spray.radius, 1.0..=256.0).text("spray radius"));
+            ui.add(egui::Slider::new(&mut spray.dot_radius, 0.5..=16.0).text("dot radius"));
+            ui.add(egui::Slider::new(&mut spray.density, 1..=200).text("dots per frame"));
+            ui.add(egui::Slider::new(&mut spray.opacity, 0.0..=1.0).text("spray opacity"));
+        }
+    }
+}
+
+fn brush_ui(ui: &mut egui::Ui, brush: &mut Brush) {
+    ui.add(egui::Slider::new(&mut brush.radius, 0..=512).text("brush radius"));
+    ui.add(egui::Slider::new(&mut brush.spacing, 0.05..=2.0).text("brush spacing"));
+    ui.add(egui::Slider::new(&mut brush.hardness, 0.0..=1.0).text("brush hardness"));
+    ui.add(egui::Slider::new(&mut brush.opacity, 0.0..=1.0).text("brush opacity"));
+    ui.checkbox(&mut brush.smoothing, "Smooth strokes");
+}
+
+fn shape_ui(ui: &mut egui::Ui, shape: &mut ShapeOptions) {
+    ui.checkbox(&mut shape.filled, "Filled");
+    ui.add_enabled(
+        !shape.filled,
+        egui::Slider::new(&mut shape.outline_width, 1.0..=64.0).text("outline width"),
+    );
+}
+
+fn layers_ui(ui: &mut egui::Ui, layers: &mut LayerStack) {
+    if ui.button("Add layer").clicked() {
+        let name = format!("Layer {}", layers.layers().len() + 1);
+        layers.add(&name);
+    }
+    ui.separator();
+    let mut active = layers.active();
+    let mut action = None;
+    // Top layer first, like in most painting tools.
+    for (i, layer) in layers.layers_mut().iter_mut().enumerate().rev() {
+        ui.push_id(i, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut active, i, "");
+                ui.checkbox(&mut layer.visible, "Visible");
+                ui.checkbox(&mut layer.locked, "Locked");
+                ui.add(egui::TextEdit::singleline(&mut layer.name).desired_width(100.0));
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("opacity"));
+                egui::ComboBox::from_id_salt("blend_mode")
+                    .selected_text(layer.blend_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in BlendMode::ALL {
+                            ui.selectable_value(&mut layer.blend_mode, mode, mode.name());
+                        }
+                    });
+                ui.add(egui::Slider::new(&mut layer.transmission, 0.0..=1.0).text("transmission"));
+                ui.checkbox(&mut layer.reflective, "Mirror");
+                ui.checkbox(&mut layer.fog, "Fog");
+                height_ui(ui, &mut layer.height);
+                if ui.small_button("Up").clicked() {
+                    action = Some((i, LayerAction::MoveUp));
+                }
+                if ui.small_button("Down").clicked() {
+                    action = Some((i, LayerAction::MoveDown));
+                }
+                if ui.small_button("Delete").clicked() {
+                    action = Some((i, LayerAction::Remove));
+                }
+            });
+        });
+        ui.separator();
+    }
+    layers.set_active(active);
+    match action {
+        Some((i, LayerAction::MoveUp)) => layers.move_up(i),
+        Some((i, LayerAction::MoveDown)) => layers.move_down(i),
+        Some((i, LayerAction::Remove)) => layers.remove(i),
+        None => {}
+    }
+}
+
+/// Toggles between an occluder taller than any light and one lights above can shine over.
+fn height_ui(ui: &mut egui::Ui, height: &mut f32) {
+    const HINT: &str =
+        "Only analytic lights shine over, painted emitters are blocked at any height";
+    let mut tall = *height >= FULL_HEIGHT;
+    if ui.checkbox(&mut tall, "Tall").on_hover_text(HINT).changed() {
+        *height = if tall { FULL_HEIGHT } else { 0.1 };
+    }
+    if !tall {
+        ui.add(egui::Slider::new(height, 0.0..=2.0).text("height"))
+            .on_hover_text(HINT);
+    }
+}
+
+enum LayerAction {
+    MoveUp,
+    MoveDown,
+    Remove,
+}
+
+fn objects_ui(ui: &mut egui::Ui, objects: &mut Vec<SceneObject>, camera: &Camera) {
+    ui.horizontal(|ui| {
+        let add = |shape| SceneObject::new(shape, camera.position, Vec2::splat(0.2), [1.0; 4]);
+        if ui.button("Add quad").clicked() {
+            objects.push(add(ObjectShape::Quad));
+        }
+        if ui.button("Add circle").clicked() {
+            objects.push(add(ObjectShape::Circle));
+        }
+    });
+    ui.separator();
+    let mut remove = None;
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            for (i, object) in objects.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(match object.shape {
+                            ObjectShape::Quad => "Quad",
+                            ObjectShape::Circle => "Circle",
+                            ObjectShape::Sprite(_) => "Sprite",
+                        });
+                        ui.add(egui::DragValue::new(&mut object.position.x).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut object.position.y).speed(0.01));
+                        ui.color_edit_button_rgba_unmultiplied(&mut object.color);
+                        if ui.small_button("Delete").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut object.scale.x).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut object.scale.y).speed(0.01));
+                        ui.drag_angle(&mut object.rotation);
+                        ui.add(
+                            egui::Slider::new(&mut object.emission, 0.0..=10.0).text("emission"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut object.transmission, 0.0..=1.0)
+                                .text("transmission"),
+                        );
+                        ui.checkbox(&mut object.reflective, "Mirror");
+                        height_ui(ui, &mut object.height);
+                    });
+                });
+                ui.separator();
+            }
+        });
+    if let Some(i) = remove {
+        objects.remove(i);
+    }
+}
+
+fn lit_sprites_ui(ui: &mut egui::Ui, sprites: &mut Vec<LitSprite>, camera: &Camera) {
+    if ui.button("Add demo sprite").clicked() {
+        sprites.push(LitSprite::new(
+            LitSpriteId::DEMO,
+            camera.position,
+            Vec2::splat(0.2),
+        ));
+    }
+    ui.label("Shown over \"RadianceCascades\".");
+    ui.separator();
+    let mut remove = None;
+    for (i, sprite) in sprites.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut sprite.position.x).speed(0.01));
+                ui.add(egui::DragValue::new(&mut sprite.position.y).speed(0.01));
+                ui.add(egui::DragValue::new(&mut sprite.scale.x).speed(0.01));
+                ui.add(egui::DragValue::new(&mut sprite.scale.y).speed(0.01));
+                ui.drag_angle(&mut sprite.rotation);
+                if ui.small_button("Delete").clicked() {
+                    remove = Some(i);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgba_unmultiplied(&mut sprite.color);
+                ui.add(
+                    egui::Slider::new(&mut sprite.normal_strength, 0.0..=2.0)
+                        .text("normal strength"),
+                );
+            });
+        });
+        ui.separator();
+    }
+    if let Some(i) = remove {
+        sprites.remove(i);
+    }
+}
+
+fn lights_ui(ui: &mut egui::Ui, lights: &mut Vec<Light>, camera: &Camera) {
+    ui.horizontal_wrapped(|ui| {
+        for kind in LightKind::ALL {
+            if ui.button(format!("Add {}", kind.name())).clicked() {
+                lights.push(Light::new(kind, camera.position));
+            }
+        }
+    });
+    ui.separator();
+    let mut remove = None;
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            for (i, light) in lights.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut light.enabled, light.kind.name());
+                        ui.add(egui::DragValue::new(&mut light.position.x).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut light.position.y).speed(0.01));
+                        ui.color_edit_button_rgb(&mut light.color);
+                        if ui.small_button("Delete").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.add(egui::Slider::new(&mut light.intensity, 0.0..=10.0).text("intensity"));
+                    if light.kind != LightKind::Sun {
+                        ui.add(egui::Slider::new(&mut light.radius, 0.01..=4.0).text("radius"));
+                    }
+                    if light.kind != LightKind::Point {
+                        ui.horizontal(|ui| {
+                            ui.drag_angle(&mut light.direction);
+                            ui.label("direction");
+                        });
+                    }
+                    match light.kind {
+                        LightKind::Spot => {
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut light.cone_angle,
+                                    0.01..=std::f32::consts::PI,
+                                )
+                                .text("cone angle"),
+                            );
+                        }
+                        LightKind::Line => {
+                            ui.add(egui::Slider::new(&mut light.size.x, 0.0..=2.0).text("length"));
+                        }
+                        LightKind::Area => {
+                            ui.add(egui::Slider::new(&mut light.size.x, 0.0..=2.0).text("width"));
+                            ui.add(egui::Slider::new(&mut light.size.y, 0.0..=2.0).text("height"));
+                        }
+                        LightKind::Point | LightKind::Sun => {}
+                    }
+                    if light.kind == LightKind::Sun {
+                        ui.add(
+                            egui::Slider::new(&mut light.source_radius, 0.0..=0.3)
+                                .text("angular radius"),
+                        );
+                    } else {
+                        ui.add(
+                            egui::Slider::new(&mut light.source_radius, 0.0..=0.5)
+                                .text("source radius"),
+                        );
+                    }
+                    if light.kind == LightKind::Sun {
+                        ui.add(
+                            egui::Slider::new(
+                                &mut light.elevation,
+                                0.0..=std::f32::consts::FRAC_PI_2,
+                            )
+                            .text("elevation angle"),
+                        );
+                    } else {
+                        ui.add(
+                            egui::Slider::new(&mut light.elevation, 0.0..=2.0).text("elevation"),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("animation")
+                            .selected_text(light.animation.name())
+                            .show_ui(ui, |ui| {
+                                for animation in LightAnimation::ALL {
+                                    ui.selectable_value(
+                                        &mut light.animation,
+                                        animation,
+                                        animation.name(),
+                                    );
+                                }
+                            });
+                        if light.animation != LightAnimation::None {
+                            ui.add(
+                                egui::DragValue::new(&mut light.animation_speed)
+                                    .speed(0.01)
+                                    .prefix("speed "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut light.animation_amount)
+                                    .speed(0.01)
+                                    .prefix("amount "),
+                            );
+                        }
+                    });
+                });
+                ui.separator();
+            }
+        });
+    if let Some(i) = remove {
+        lights.remove(i);
+    }
+}
+
+fn history_ui(ui: &mut egui::Ui, history: &mut History) {
+    ui.horizontal(|ui| {
+        if ui.button("Undo").clicked() {
+            history.undo();
+        }
+        if ui.button("Redo").clicked() {
+            history.redo();
+        }
+    });
+    ui.add(egui::Slider::new(&mut history.memory_budget_mb, 16..=4096).text("memory budget, MB"));
+    ui.label(format!(
+        "Used: {:.1} MB",
+        history.size_bytes() as f64 / (1024.0 * 1024.0)
+    ));
+    ui.separator();
+    let position = history.position();
+    let mut jump = None;
+    egui::ScrollArea::vertical()
+        .max_height(300.0)
+        .show(ui, |ui| {
+            if ui.selectable_label(position == 0, "Initial").clicked() {
+                jump = Some(0);
+            }
+            for (i, label) in history.labels().enumerate() {
+                let applied = i < position;
+                let text = egui::RichText::new(label);
+                let text = if applied { text } else { text.weak() };
+                if ui.selectable_label(i + 1 == position, text).clicked() {
+                    jump = Some(i + 1);
+                }
+            }
+        });
+    if let Some(jump) = jump {
+        history.jump_to(jump);
+    }
 }
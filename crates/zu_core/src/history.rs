@@ -0,0 +1,413 @@
+use std::collections::{HashMap, VecDeque};
+
+use glam::IVec2;
+use wgpu::{CommandEncoder, Device, Texture};
+
+use crate::{
+    texture_manager::{
+        TextureManager,
+        dirty_rect::DirtyRect,
+        textures::{EngineTexture, ManagedTexture, scene_texture::SceneTexture},
+    },
+    world::{ChunkedWorld, texel_rect},
+};
+
+const TEXEL_SIZE: u64 = std::mem::size_of::<[f32; 4]>() as u64;
+
+/// Contents of an edited region of one texture before and after the edit.
+struct Patch {
+    texture: String,
+    /// World texel of the top left corner, the patch stays put when the canvas moves.
+    min: IVec2,
+    before: Texture,
+    after: Texture,
+}
+
+impl Patch {
+    fn area(&self) -> u64 {
+        self.before.width() as u64 * self.before.height() as u64
+    }
+}
+
+struct HistoryEntry {
+    label: String,
+    patches: Vec<Patch>,
+}
+
+impl HistoryEntry {
+    fn size_bytes(&self) -> u64 {
+        self.patches
+            .iter()
+            .map(|patch| 2 * patch.area() * TEXEL_SIZE)
+            .sum()
+    }
+}
+
+enum HistoryAction {
+    Commit(String),
+    Undo,
+    Redo,
+}
+
+/// Undo/redo stack of edits to scene textures, e.g. the layers.
+///
+/// A copy of every tracked texture as of the last commit is kept on the GPU. Committing
+/// snapshots only the regions edited since then, so the cost of an entry is proportional
+/// to the area it touched. Entries are kept in world texels, undoing an edit that scrolled
+/// off the canvas restores it in the world chunks.
+pub struct History {
+    /// Entries above this many megabytes are dropped, oldest first.
+    pub memory_budget_mb: u32,
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    backups: HashMap<String, Texture>,
+    /// Edits snapshotted when the canvas moved, part of the next commit.
+    uncommitted: Vec<Patch>,
+    pending: Vec<HistoryAction>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            memory_budget_mb: 256,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            backups: HashMap::new(),
+            uncommitted: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl History {
+    /// Records everything painted since the previous commit as one entry.
+    pub fn commit(&mut self, label: &str) {
+        self.pending.push(HistoryAction::Commit(label.to_string()));
+    }
+
+    pub fn undo(&mut self) {
+        self.pending.push(HistoryAction::Undo);
+    }
+
+    pub fn redo(&mut self) {
+        self.pending.push(HistoryAction::Redo);
+    }
+
+    /// Undoes or redoes until `position` entries are applied.
+    pub fn jump_to(&mut self, position: usize) {
+        let current = self.position();
+        if position < current {
+            (position..current).for_each(|_| self.undo());
+        } else {
+            (current..position).for_each(|_| self.redo());
+        }
+    }
+
+    /// Number of applied entries, `labels()[..position()]` are the ones that can be undone.
+    pub fn position(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.undo
+            .iter()
+            .chain(self.redo.iter().rev())
+            .map(|entry| entry.label.as_str())
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.undo
+            .iter()
+            .chain(self.redo.iter())
+            .map(HistoryEntry::size_bytes)
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.backups.clear();
+        self.uncommitted.clear();
+    }
+
+    /// Stops tracking a texture and drops its part of every entry.
+    pub fn forget(&mut self, texture: &str) {
+        self.backups.remove(texture);
+        self.uncommitted.retain(|patch| patch.texture != texture);
+        for entry in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            entry.patches.retain(|patch| patch.texture != texture);
+        }
+        self.undo.retain(|entry| !entry.patches.is_empty());
+        self.redo.retain(|entry| !entry.patches.is_empty());
+    }
+
+    /// Starts tracking `name`, or resyncs it after it was resized. Has to run before this
+    /// frame's edits are applied. Entries are kept, they are restored in world texels.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        name: &str,
+        scene: &SceneTexture,
+    ) {
+        let size = scene.texture().size();
+        if self
+            .backups
+            .get(name)
+            .is_some_and(|backup| backup.size() == size)
+        {
+            return;
+        }
+        let backup = create_snapshot(device, "Scene history backup", size.width, size.height);
+        let full = DirtyRect::full(size.width, size.height);
+        copy_region(encoder, scene.texture(), full, &backup, 0, 0);
+        self.backups.insert(name.to_string(), backup);
+    }
+
+    /// Applies queued commits, undos and redos to the tracked textures. Parts of the
+    /// entries off the canvas are restored in the chunks of `world`.
+    pub fn apply(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        world: &mut ChunkedWorld,
+    ) {
+        puffin::profile_function!();
+        let origin = world.origin();
+        for action in std::mem::take(&mut self.pending) {
+            match action {
+                HistoryAction::Commit(label) => {
+                    self.commit_edits(device, encoder, texture_manager, origin, label);
+                }
+                HistoryAction::Undo => {
+                    // Painting that was not committed yet is undone first.
+                    self.commit_edits(device, encoder, texture_manager, origin, "Edit".into());
+                    if let Some(entry) = self.undo.pop_back() {
+                        // Patches of one entry can overlap, the earliest holds the original.
+                        for patch in entry.patches.iter().rev() {
+                            self.restore(
+                                device,
+                                encoder,
+                                texture_manager,
+                                world,
+                                patch,
+                                &patch.before,
+                            );
+                        }
+                        self.redo.push(entry);
+                    }
+                }
+                HistoryAction::Redo => {
+                    if let Some(entry) = self.redo.pop() {
+                        for patch in &entry.patches {
+                            self.restore(
+                                device,
+                                encoder,
+                                texture_manager,
+                                world,
+                                patch,
+                                &patch.after,
+                            );
+                        }
+                        self.undo.push_back(entry);
+                    }
+                }
+            }
+        }
+        self.enforce_budget();
+    }
+
+    /// Follows the tracked textures being moved away from the world texel `origin`, e.g.
+    /// when the world window scrolls. `refill` replaces the contents. Uncommitted edits
+    /// are snapshotted first and become part of the next commit.
+    pub fn shift(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        origin: IVec2,
+        refill: impl FnOnce(&mut CommandEncoder, &mut TextureManager),
+    ) {
+        let patches = self.snapshot_edits(device, encoder, texture_manager, origin);
+        self.uncommitted.extend(patches);
+        refill(encoder, texture_manager);
+        for (name, backup) in &self.backups {
+            if let Some(scene) = texture_manager.get_texture(name) {
+                let full = DirtyRect::full(backup.width(), backup.height());
+                copy_region(encoder, scene.texture(), full, backup, 0, 0);
+            }
+        }
+    }
+
+    fn commit_edits(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        origin: IVec2,
+        label: String,
+    ) {
+        let mut patches = std::mem::take(&mut self.uncommitted);
+        patches.extend(self.snapshot_edits(device, encoder, texture_manager, origin));
+        if patches.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(HistoryEntry { label, patches });
+    }
+
+    /// Snapshots the regions edited since the last snapshot and updates the backups.
+    fn snapshot_edits(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        origin: IVec2,
+    ) -> Vec<Patch> {
+        let mut patches = Vec::new();
+        for (name, backup) in &self.backups {
+            let Some(scene) = texture_manager
+                .get_texture_mut(name)
+                .and_then(ManagedTexture::scene_mut)
+            else {
+                continue;
+            };
+            let Some(region) = scene.take_edited_rect() else {
+                continue;
+            };
+            let before = create_snapshot(device, "History before", region.width(), region.height());
+            let after = create_snapshot(device, "History after", region.width(), region.height());
+            copy_region(encoder, backup, region, &before, 0, 0);
+            copy_region(encoder, scene.texture(), region, &after, 0, 0);
+            copy_region(
+                encoder,
+                scene.texture(),
+                region,
+                backup,
+                region.min_x,
+                region.min_y,
+            );
+            patches.push(Patch {
+                texture: name.clone(),
+                min: origin + IVec2::new(region.min_x as i32, region.min_y as i32),
+                before,
+                after,
+            });
+        }
+        patches
+    }
+
+    /// Copies `snapshot` of `patch` back, to the canvas where it overlaps it and to the
+    /// world chunks everywhere.
+    fn restore(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        world: &mut ChunkedWorld,
+        patch: &Patch,
+        snapshot: &Texture,
+    ) {
+        let full = DirtyRect::full(snapshot.width(), snapshot.height());
+        world.write(device, encoder, &patch.texture, snapshot, full, patch.min);
+        let (Some(backup), Some(scene)) = (
+            self.backups.get(&patch.texture),
+            texture_manager
+                .get_texture_mut(&patch.texture)
+                .and_then(ManagedTexture::scene_mut),
+        ) else {
+            return;
+        };
+        let canvas = scene.texture().size();
+        let canvas_min = world.origin();
+        let canvas_max = canvas_min + IVec2::new(canvas.width as i32, canvas.height as i32);
+        let size = IVec2::new(snapshot.width() as i32, snapshot.height() as i32);
+        let min = patch.min.max(canvas_min);
+        let max = (patch.min + size).min(canvas_max);
+        if min.cmpge(max).any() {
+            return;
+        }
+        let src = texel_rect(min - patch.min, max - patch.min);
+        let dst = texel_rect(min - canvas_min, max - canvas_min);
+        copy_region(
+            encoder,
+            snapshot,
+            src,
+            scene.texture(),
+            dst.min_x,
+            dst.min_y,
+        );
+        copy_region(encoder, snapshot, src, backup, dst.min_x, dst.min_y);
+        scene.mark_changed(dst);
+    }
+
+    fn enforce_budget(&mut self) {
+        let budget = self.memory_budget_mb as u64 * 1024 * 1024;
+        let mut size = self.size_bytes();
+        while size > budget {
+            let Some(entry) = self
+                .undo
+                .pop_front()
+                .or_else(|| (!self.redo.is_empty()).then(|| self.redo.remove(0)))
+            else {
+                break;
+            };
+            size -= entry.size_bytes();
+        }
+    }
+}
+
+pub fn create_snapshot(device: &Device, label: &str, width: u32, height: u32) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+pub fn copy_region(
+    encoder: &mut CommandEncoder,
+    src: &Texture,
+    region: DirtyRect,
+    dst: &Texture,
+    dst_x: u32,
+    dst_y: u32,
+) {
+    encoder.copy_texture_to_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: src,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: region.min_x,
+                y: region.min_y,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyTextureInfo {
+            texture: dst,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: dst_x,
+                y: dst_y,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::Extent3d {
+            width: region.width(),
+            height: region.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -0,0 +1,160 @@
+/// Height of surfaces no light shines over, in world units, far above any light. Finite
+/// so the raymarcher can do arithmetic on it, the composite clamps heights to it.
+pub const FULL_HEIGHT: f32 = 1.0e6;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal = 0,
+    Add = 1,
+    Multiply = 2,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 3] = [BlendMode::Normal, BlendMode::Add, BlendMode::Multiply];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Add => "Add",
+            BlendMode::Multiply => "Multiply",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    /// Locked layers ignore painting.
+    pub locked: bool,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    /// Fraction of the light passing through the painted texels, tinted by their color.
+    /// Translucent texels only emit the rest.
+    pub transmission: f32,
+    /// Painted texels mirror the light hitting them, tinted by their color, instead of
+    /// glowing.
+    pub reflective: bool,
+    /// Painted alpha is fog density rather than surfaces, painted color tints the light
+    /// the fog scatters.
+    pub fog: bool,
+    /// How far the painted surfaces reach up, in world units. Analytic lights above them
+    /// shine over them, painted emitters do not, `FULL_HEIGHT` by default.
+    pub height: f32,
+    id: u32,
+}
+
+impl Layer {
+    /// Name of the scene texture holding the layer contents.
+    pub fn texture_name(&self) -> String {
+        format!("Layer{}", self.id)
+    }
+}
+
+/// Paintable layers, bottom to top, composited into the "SceneTexture".
+#[derive(Debug)]
+pub struct LayerStack {
+    layers: Vec<Layer>,
+    active: usize,
+    next_id: u32,
+    removed: Vec<String>,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        let mut stack = Self {
+            layers: Vec::new(),
+            active: 0,
+            next_id: 0,
+            removed: Vec::new(),
+        };
+        stack.add("Background");
+        stack
+    }
+}
+
+impl LayerStack {
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Layer] {
+        &mut self.layers
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.active]
+    }
+
+    pub fn set_active(&mut self, index: usize) {
+        if index < self.layers.len() {
+            self.active = index;
+        }
+    }
+
+    /// Adds a layer above the active one and makes it active.
+    pub fn add(&mut self, name: &str) {
+        let layer = Layer {
+            name: name.to_string(),
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            transmission: 0.0,
+            reflective: false,
+            fog: false,
+            height: FULL_HEIGHT,
+            id: self.next_id,
+        };
+        self.next_id += 1;
+        let index = if self.layers.is_empty() {
+            0
+        } else {
+            self.active + 1
+        };
+        self.layers.insert(index, layer);
+        self.active = index;
+    }
+
+    /// Removes the layer at `index`, the last remaining layer is kept.
+    pub fn remove(&mut self, index: usize) {
+        if self.layers.len() <= 1 || index >= self.layers.len() {
+            return;
+        }
+        let layer = self.layers.remove(index);
+        self.removed.push(layer.texture_name());
+        if self.active > index {
+            self.active -= 1;
+        }
+        self.active = self.active.min(self.layers.len() - 1);
+    }
+
+    /// Swaps the layer at `index` with the one above it.
+    pub fn move_up(&mut self, index: usize) {
+        if index + 1 >= self.layers.len() {
+            return;
+        }
+        self.layers.swap(index, index + 1);
+        if self.active == index {
+            self.active += 1;
+        } else if self.active == index + 1 {
+            self.active -= 1;
+        }
+    }
+
+    pub fn move_down(&mut self, index: usize) {
+        if index > 0 {
+            self.move_up(index - 1);
+        }
+    }
+
+    /// Texture names of the layers removed since the last call.
+    pub fn take_removed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.removed)
+    }
+}
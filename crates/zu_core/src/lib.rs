@@ -1,12 +1,17 @@
 pub mod app;
 pub mod app_state;
+pub mod brush;
 pub mod camera;
 pub mod egui_tools;
 pub mod gui;
+pub mod history;
+pub mod layers;
+pub mod lights;
 pub mod render_passes;
 pub mod styles;
 pub mod texture_manager;
 pub mod widgets;
+pub mod world;
 
 pub fn start_puffin_server() {
     puffin::set_scopes_on(true); // tell puffin to collect data
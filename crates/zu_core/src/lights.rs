@@ -0,0 +1,185 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+
+use crate::world::ChunkedWorld;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Point = 0,
+    /// Point light shining along `direction` within `cone_angle`.
+    Spot = 1,
+    /// Segment of length `size.x` through `position`, along `direction`.
+    Line = 2,
+    /// Rectangle of `size` around `position`, rotated by `direction`.
+    Area = 3,
+    /// Parallel light travelling along `direction`, from outside the canvas.
+    Sun = 4,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 5] = [
+        LightKind::Point,
+        LightKind::Spot,
+        LightKind::Line,
+        LightKind::Area,
+        LightKind::Sun,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightKind::Point => "Point",
+            LightKind::Spot => "Spot",
+            LightKind::Line => "Line",
+            LightKind::Area => "Area",
+            LightKind::Sun => "Sun",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightAnimation {
+    None,
+    /// Circles around `position` with radius `amount`.
+    Orbit,
+    /// Intensity swings by `amount` around its value.
+    Pulse,
+    /// Intensity drops by up to `amount` at random.
+    Flicker,
+    /// `direction` turns at `speed` radians per second.
+    Rotate,
+}
+
+impl LightAnimation {
+    pub const ALL: [LightAnimation; 5] = [
+        LightAnimation::None,
+        LightAnimation::Orbit,
+        LightAnimation::Pulse,
+        LightAnimation::Flicker,
+        LightAnimation::Rotate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightAnimation::None => "None",
+            LightAnimation::Orbit => "Orbit",
+            LightAnimation::Pulse => "Pulse",
+            LightAnimation::Flicker => "Flicker",
+            LightAnimation::Rotate => "Rotate",
+        }
+    }
+}
+
+/// Light evaluated analytically by the compute radiance pass, in world units. Painted
+/// texels keep working as lights next to these.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub enabled: bool,
+    pub position: Vec2,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light has faded out, unused by the sun.
+    pub radius: f32,
+    /// Counterclockwise angle in radians.
+    pub direction: f32,
+    /// Half angle of the spot light cone in radians.
+    pub cone_angle: f32,
+    pub size: Vec2,
+    /// Radius of the emitter in world units, rounding off points, lines and areas. The
+    /// angular radius in radians for the sun. Wider emitters cast wider penumbrae.
+    pub source_radius: f32,
+    /// Height above the ground in world units, the angle above the horizon in radians for
+    /// the sun. Lights shine over occluders lower than the line to them.
+    pub elevation: f32,
+    pub animation: LightAnimation,
+    pub animation_speed: f32,
+    pub animation_amount: f32,
+}
+
+impl Light {
+    pub fn new(kind: LightKind, position: Vec2) -> Self {
+        Self {
+            kind,
+            enabled: true,
+            position,
+            color: [1.0; 3],
+            intensity: 1.0,
+            radius: 0.5,
+            direction: -std::f32::consts::FRAC_PI_2,
+            cone_angle: 0.5,
+            size: Vec2::new(0.2, 0.05),
+            source_radius: match kind {
+                LightKind::Sun => 0.01,
+                _ => 0.02,
+            },
+            elevation: 0.0,
+            animation: LightAnimation::None,
+            animation_speed: 1.0,
+            animation_amount: 0.1,
+        }
+    }
+
+    /// The light as it is `time` seconds into its animation, flickering in the pattern of
+    /// `seed`.
+    pub fn animated(&self, time: f64, seed: u32) -> Light {
+        let phase = (time * self.animation_speed as f64) as f32;
+        let amount = self.animation_amount;
+        let mut light = *self;
+        match self.animation {
+            LightAnimation::None => {}
+            LightAnimation::Orbit => light.position += Vec2::from_angle(phase) * amount,
+            LightAnimation::Pulse => light.intensity *= (1.0 + amount * phase.sin()).max(0.0),
+            LightAnimation::Flicker => {
+                // Cheap hash of the current step, a new level every 1 / speed seconds.
+                let step = phase.floor() + (seed % 4096) as f32 * 0.618_034;
+                let noise = ((step * 12.9898).sin() * 43758.547).fract().abs();
+                light.intensity *= (1.0 - amount * noise).max(0.0);
+            }
+            LightAnimation::Rotate => light.direction += phase,
+        }
+        light
+    }
+
+    /// The light in canvas texels, as read by the radiance shader.
+    pub fn canvas_data(&self, world: &ChunkedWorld) -> LightData {
+        let texels_per_unit = world.grid().texels_per_unit;
+        // World y goes up, canvas rows go down.
+        let (sin, cos) = self.direction.sin_cos();
+        LightData {
+            position: world.world_to_canvas(self.position).into(),
+            axis: [cos, -sin],
+            half_size: (self.size * 0.5 * texels_per_unit).into(),
+            radius: self.radius * texels_per_unit,
+            cos_cone: self.cone_angle.cos(),
+            color: self.color.map(|channel| channel * self.intensity),
+            kind: self.kind as u32,
+            source_radius: match self.kind {
+                LightKind::Sun => self.source_radius.tan(),
+                _ => self.source_radius * texels_per_unit,
+            },
+            height: match self.kind {
+                LightKind::Sun => self.elevation.tan() / texels_per_unit,
+                _ => self.elevation,
+            },
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+pub struct LightData {
+    pub position: [f32; 2],
+    pub axis: [f32; 2],
+    pub half_size: [f32; 2],
+    pub radius: f32,
+    pub cos_cone: f32,
+    pub color: [f32; 3],
+    pub kind: u32,
+    /// In texels, the tangent of the angular radius for the sun.
+    pub source_radius: f32,
+    /// In world units, what the rays of the sun rise per texel.
+    pub height: f32,
+    pub _padding: [f32; 2],
+}
@@ -0,0 +1,193 @@
+use bytemuck::{Pod, Zeroable, bytes_of};
+use glam::Vec2;
+use wgpu::{CommandEncoder, ComputePipelineDescriptor, Device, PushConstantRange, ShaderStages};
+
+use crate::texture_manager::{TextureManager, dirty_rect::DirtyRect, textures::EngineTexture};
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StampMode {
+    /// Lerps every channel towards the brush color, alpha included.
+    Mix = 0,
+    /// Fades every channel towards zero, clearing alpha entirely under full coverage.
+    Erase = 1,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StampShape {
+    Circle = 0,
+    /// Capsule of `radius` from `center` to `extent`.
+    Line = 1,
+    /// Rectangle around `center` with half size `extent`.
+    Rect = 2,
+    /// Ellipse around `center` with radii `extent`.
+    Ellipse = 3,
+}
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+pub struct BrushStamp {
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub hardness: f32,
+    pub color: [f32; 4],
+    pub opacity: f32,
+    pub mode: u32,
+    pub shape: u32,
+    /// Outline width in texels, `0` fills the shape.
+    pub outline: f32,
+    pub extent: [f32; 2],
+    pub _pad: [f32; 2],
+}
+
+impl BrushStamp {
+    pub fn circle(center: Vec2, radius: f32, color: [f32; 4]) -> Self {
+        Self {
+            center: center.into(),
+            radius,
+            hardness: 1.0,
+            color,
+            opacity: 1.0,
+            mode: StampMode::Mix as u32,
+            shape: StampShape::Circle as u32,
+            outline: 0.0,
+            extent: [0.0; 2],
+            _pad: [0.0; 2],
+        }
+    }
+
+    pub fn line(start: Vec2, end: Vec2, radius: f32, color: [f32; 4]) -> Self {
+        Self {
+            shape: StampShape::Line as u32,
+            extent: end.into(),
+            ..Self::circle(start, radius, color)
+        }
+    }
+
+    /// Rectangle or ellipse spanning the two corners of `a` and `b`.
+    pub fn shape_between(shape: StampShape, a: Vec2, b: Vec2, color: [f32; 4]) -> Self {
+        Self {
+            shape: shape as u32,
+            extent: ((b - a).abs() * 0.5).into(),
+            ..Self::circle((a + b) * 0.5, 0.0, color)
+        }
+    }
+
+    pub fn with_mode(self, mode: StampMode) -> Self {
+        Self {
+            mode: mode as u32,
+            ..self
+        }
+    }
+
+    /// Moves the stamp by `offset` texels.
+    pub fn translated(self, offset: Vec2) -> Self {
+        let extent = if self.shape == StampShape::Line as u32 {
+            (Vec2::from(self.extent) + offset).into()
+        } else {
+            self.extent
+        };
+        Self {
+            center: (Vec2::from(self.center) + offset).into(),
+            extent,
+            ..self
+        }
+    }
+
+    /// Texels the stamp can touch, clamped to `width`x`height`. `None` if it is fully outside.
+    pub fn bounds(&self, width: u32, height: u32) -> Option<DirtyRect> {
+        let center = Vec2::from(self.center);
+        let extent = Vec2::from(self.extent);
+        let (min, max) = if self.shape == StampShape::Line as u32 {
+            (center.min(extent), center.max(extent))
+        } else if self.shape == StampShape::Circle as u32 {
+            (center, center)
+        } else {
+            (center - extent, center + extent)
+        };
+        let pad = self.radius + self.outline + 1.0;
+        let min_x = (min.x - pad).floor().max(0.0);
+        let min_y = (min.y - pad).floor().max(0.0);
+        let max_x = (max.x + pad).ceil().min(width as f32);
+        let max_y = (max.y + pad).ceil().min(height as f32);
+        let rect = DirtyRect::new(min_x as u32, min_y as u32, max_x as u32, max_y as u32);
+        (!rect.is_empty()).then_some(rect)
+    }
+}
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+struct BrushConstants {
+    stamp: BrushStamp,
+    region_min: [i32; 2],
+    region_max: [i32; 2],
+}
+
+pub struct BrushPass {
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+impl BrushPass {
+    pub fn new(device: &Device, texture_manager: &TextureManager) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/brush_pass.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Brush pass layout descriptor"),
+            bind_group_layouts: &[texture_manager.get_compute_mut_bind_group_layout()],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<BrushConstants>() as u32,
+            }],
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Brush pass"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        BrushPass { compute_pipeline }
+    }
+
+    /// Stamps directly into `target`, one dispatch per stamp covering only its bounds.
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &dyn EngineTexture,
+        stamps: &[BrushStamp],
+        width: u32,
+        height: u32,
+    ) {
+        if stamps.is_empty() {
+            return;
+        }
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Brush pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, target.compute_mut_group_f32(), &[]);
+        for stamp in stamps {
+            let Some(region) = stamp.bounds(width, height) else {
+                continue;
+            };
+            compute_pass.set_push_constants(
+                0,
+                bytes_of(&BrushConstants {
+                    stamp: *stamp,
+                    region_min: [region.min_x as i32, region.min_y as i32],
+                    region_max: [region.max_x as i32, region.max_y as i32],
+                }),
+            );
+            compute_pass.dispatch_workgroups(
+                region.width().div_ceil(16),
+                region.height().div_ceil(16),
+                1,
+            );
+        }
+    }
+}
@@ -2,7 +2,10 @@ use wgpu::{
     CommandEncoder, Device,
 };
 
-use crate::{render_passes::quad_vertex::QuadVertexRenderPass, texture_manager::TextureManager};
+use crate::{
+    render_passes::quad_vertex::QuadVertexRenderPass,
+    texture_manager::{TextureManager, dirty_rect::DirtyRect},
+};
 use crate::{texture_manager::textures::EngineTexture, vertex_state_for_quad};
 
 pub struct DistantFieldPass {
@@ -80,6 +83,7 @@ impl DistantFieldPass {
         device: &Device,
         texture_manager: &TextureManager,
         quad_render_pass: &QuadVertexRenderPass,
+        region: DirtyRect,
     ) {
         let distance_texture = texture_manager
             .get_texture("DistanceField")
@@ -90,7 +94,7 @@ impl DistantFieldPass {
                 view: distance_texture.view(),
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Store,
                 },
                 depth_slice: Default::default(),
@@ -101,6 +105,7 @@ impl DistantFieldPass {
         });
 
         render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_scissor_rect(region.min_x, region.min_y, region.width(), region.height());
         render_pass.set_bind_group(
             0,
             texture_manager
@@ -0,0 +1,292 @@
+use bytemuck::{Pod, Zeroable, bytes_of};
+use glam::IVec2;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, CommandEncoder, ComputePipeline,
+    ComputePipelineDescriptor, Device, PushConstantRange, Queue, ShaderStages, Texture,
+};
+
+use crate::texture_manager::{
+    TextureManager,
+    dirty_rect::DirtyRect,
+    readback::Readback,
+    textures::{EngineTexture, ManagedTexture},
+};
+
+/// Spread passes dispatched per frame while a fill grows.
+const SPREAD_PASSES: u32 = 16;
+/// Bytes of the status read back: the changed flag and the bounds.
+const STATUS_READ_SIZE: u64 = 5 * 4;
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+struct FloodFillConstants {
+    color: [f32; 4],
+    seed: [u32; 2],
+    tolerance: f32,
+    _pad: f32,
+}
+
+/// `FillStatus` of the shader before the seed is marked.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+struct FillStatus {
+    changed: u32,
+    min: [u32; 2],
+    max: [u32; 2],
+    _pad: [u32; 3],
+    seed_color: [f32; 4],
+}
+
+struct FloodFill {
+    layer: String,
+    /// World origin of the canvas the seed is relative to.
+    origin: IVec2,
+    constants: FloodFillConstants,
+    started: bool,
+    /// A status copy of this fill is being read back.
+    awaiting_status: bool,
+}
+
+/// Flood fills a layer on the GPU. The fill spreads from the seed over a few frames, it
+/// is painted once a frame's spread passes reach no new texel.
+pub struct FloodFillPass {
+    init_pipeline: ComputePipeline,
+    spread_pipeline: ComputePipeline,
+    fill_pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    status: Buffer,
+    /// Texels reached by the fill so far.
+    mask: Option<(Texture, BindGroup)>,
+    readback: Readback,
+    fill: Option<FloodFill>,
+}
+
+impl FloodFillPass {
+    pub fn new(device: &Device, texture_manager: &TextureManager) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/flood_fill.wgsl"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Flood fill bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadWrite,
+                        format: wgpu::TextureFormat::R32Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Flood fill pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<FloodFillConstants>() as u32,
+            }],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Flood fill pass"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: Default::default(),
+            })
+        };
+        let status = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Flood fill status"),
+            size: std::mem::size_of::<FillStatus>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            init_pipeline: pipeline("init_main"),
+            spread_pipeline: pipeline("spread_main"),
+            fill_pipeline: pipeline("fill_main"),
+            bind_group_layout,
+            status,
+            mask: None,
+            readback: Readback::new("Flood fill status readback"),
+            fill: None,
+        }
+    }
+
+    /// Starts filling `layer` from `seed`, replacing a fill still in progress.
+    pub fn start(
+        &mut self,
+        layer: String,
+        origin: IVec2,
+        seed: (u32, u32),
+        color: [f32; 4],
+        tolerance: f32,
+    ) {
+        self.fill = Some(FloodFill {
+            layer,
+            origin,
+            constants: FloodFillConstants {
+                color,
+                seed: [seed.0, seed.1],
+                tolerance,
+                _pad: 0.0,
+            },
+            started: false,
+            awaiting_status: false,
+        });
+    }
+
+    /// Advances the fill in progress. Returns whether it was painted this frame, its
+    /// bounds are then recorded as an edit of the layer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &mut TextureManager,
+        origin: IVec2,
+        width: u32,
+        height: u32,
+    ) -> bool {
+        puffin::profile_function!();
+        let status = self.readback.take(device);
+        let Some(mut fill) = self.fill.take() else {
+            return false;
+        };
+        let seed = fill.constants.seed;
+        // The seed only means something on the canvas it was clicked on.
+        if fill.origin != origin || seed[0] >= width || seed[1] >= height {
+            return false;
+        }
+        let Some(scene) = texture_manager
+            .get_texture_mut(&fill.layer)
+            .and_then(ManagedTexture::scene_mut)
+        else {
+            return false;
+        };
+        if !fill.started {
+            let resized = self
+                .mask
+                .as_ref()
+                .is_none_or(|(mask, _)| (mask.width(), mask.height()) != (width, height));
+            if resized {
+                self.mask = Some(self.create_mask(device, width, height));
+            }
+            queue.write_buffer(
+                &self.status,
+                0,
+                bytes_of(&FillStatus {
+                    changed: 0,
+                    min: [u32::MAX; 2],
+                    max: [0; 2],
+                    _pad: [0; 3],
+                    seed_color: [0.0; 4],
+                }),
+            );
+        }
+        let Some((_, mask_bind_group)) = &self.mask else {
+            return false;
+        };
+        let workgroups = (width.div_ceil(16), height.div_ceil(16));
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Flood fill pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_bind_group(0, scene.compute_mut_group_f32(), &[]);
+        compute_pass.set_bind_group(1, mask_bind_group, &[]);
+        compute_pass.set_push_constants(0, bytes_of(&fill.constants));
+
+        // A status copied for a fill that was replaced since is dropped.
+        if let Some(status) = status.filter(|_| std::mem::take(&mut fill.awaiting_status)) {
+            let status: Vec<u32> = bytemuck::pod_collect_to_vec(&status);
+            // Nothing was reached in the frame the status was copied in.
+            if status[0] == 0 {
+                compute_pass.set_pipeline(&self.fill_pipeline);
+                compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+                drop(compute_pass);
+                scene.record_edit(DirtyRect::new(
+                    status[1],
+                    status[2],
+                    status[3] + 1,
+                    status[4] + 1,
+                ));
+                return true;
+            }
+        }
+        if !fill.started {
+            fill.started = true;
+            compute_pass.set_pipeline(&self.init_pipeline);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        compute_pass.set_pipeline(&self.spread_pipeline);
+        for _ in 0..SPREAD_PASSES {
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        drop(compute_pass);
+        if self.readback.is_idle() {
+            // Cleared before this frame's passes, the copy tells whether they grew it.
+            queue.write_buffer(&self.status, 0, bytes_of(&0u32));
+            self.readback
+                .copy_buffer(device, encoder, &self.status, STATUS_READ_SIZE);
+            fill.awaiting_status = true;
+        }
+        self.fill = Some(fill);
+        false
+    }
+
+    /// Maps the status copied this frame, call once it was submitted.
+    pub fn submitted(&mut self) {
+        self.readback.submitted();
+    }
+
+    fn create_mask(&self, device: &Device, width: u32, height: u32) -> (Texture, BindGroup) {
+        let mask = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Flood fill mask"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Uint,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let view = mask.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Flood fill bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.status.as_entire_binding(),
+                },
+            ],
+        });
+        (mask, bind_group)
+    }
+}
@@ -5,7 +5,7 @@ use wgpu::{
     util::RenderEncoder,
 };
 
-use crate::texture_manager::{TextureManager, textures::EngineTexture};
+use crate::texture_manager::{TextureManager, dirty_rect::DirtyRect, textures::EngineTexture};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Zeroable, Pod)]
@@ -14,6 +14,8 @@ pub struct JfaConstants {
     pub texture_size: [f32; 2],
     pub u_offset: i32,
     pub _pad: f32,
+    pub region_min: [i32; 2],
+    pub region_max: [i32; 2],
 }
 
 pub struct JfaComputePass {
@@ -29,6 +31,7 @@ impl JfaComputePass {
             bind_group_layouts: &[
                 texture_manager.get_compute_bind_group_layout(),
                 texture_manager.get_compute_mut_bind_group_layout(),
+                texture_manager.get_compute_bind_group_layout(),
             ],
             push_constant_ranges: &[PushConstantRange {
                 stages: ShaderStages::COMPUTE,
@@ -55,14 +58,23 @@ impl JfaComputePass {
         passes: u32,
         width: u32,
         height: u32,
+        region: DirtyRect,
     ) {
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("JFA compute pass"),
             timestamp_writes: Default::default(),
         });
-        let wg_x = (width + 7) / 32;
-        let wg_y = (height + 7) / 32;
+        let wg_x = region.width().div_ceil(32);
+        let wg_y = region.height().div_ceil(32);
         compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(
+            2,
+            texture_manager
+                .get_texture("SceneTexture")
+                .unwrap()
+                .compute_bind_group(),
+            &[],
+        );
         for pass_i in 0..passes {
             let u_offset = 2.0_f32.powi((passes - pass_i - 1) as i32) as i32;
 
@@ -73,6 +85,8 @@ impl JfaComputePass {
                     texture_size: [width as f32, height as f32],
                     u_offset,
                     _pad: 0.0,
+                    region_min: [region.min_x as i32, region.min_y as i32],
+                    region_max: [region.max_x as i32, region.max_y as i32],
                 }),
             );
 
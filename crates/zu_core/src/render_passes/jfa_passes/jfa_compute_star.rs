@@ -1,5 +1,5 @@
 use bytemuck::{Pod, Zeroable, bytes_of};
-use rand::RngCore;
+use rand::{RngCore, SeedableRng, rngs::StdRng};
 use wgpu::{
     BindGroup, BindGroupLayout, Buffer, CommandEncoder, ComputePipelineDescriptor, Device,
     PushConstantRange, ShaderStages,
@@ -8,10 +8,9 @@ use wgpu::{
 
 use crate::texture_manager::{TextureManager, textures::EngineTexture};
 
-fn create_noise_buffer(device: &Device, width: u32, height: u32) -> Buffer {
-    let noise_data: Vec<f32> = (0..width * height)
-        .map(|_| rand::rng().next_u32() as f32)
-        .collect();
+fn create_noise_buffer(device: &Device, seed: u32, width: u32, height: u32) -> Buffer {
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    let noise_data: Vec<f32> = (0..width * height).map(|_| rng.next_u32() as f32).collect();
     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Noise Buffer"),
         contents: bytemuck::cast_slice(&noise_data),
@@ -32,12 +31,14 @@ pub struct JfaComputeStarPass {
     compute_pipeline: wgpu::ComputePipeline,
     noise_bind_group_layout: BindGroupLayout,
     noise_bind_group: BindGroup,
+    seed: u32,
 }
 
 impl JfaComputeStarPass {
     pub fn new(
         device: &Device,
         texture_manager: &mut TextureManager,
+        seed: u32,
         width: u32,
         height: u32,
     ) -> Self {
@@ -84,7 +85,7 @@ impl JfaComputeStarPass {
             layout: &noise_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: create_noise_buffer(device, width, height).as_entire_binding(),
+                resource: create_noise_buffer(device, seed, width, height).as_entire_binding(),
             }],
         });
 
@@ -92,16 +93,23 @@ impl JfaComputeStarPass {
             compute_pipeline,
             noise_bind_group_layout,
             noise_bind_group,
+            seed,
         }
     }
 
+    /// Refills the noise with the sequence of `seed`.
+    pub fn set_seed(&mut self, device: &Device, seed: u32, width: u32, height: u32) {
+        self.seed = seed;
+        self.resize(device, width, height);
+    }
+
     pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
         self.noise_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Noise Bind Group"),
             layout: &self.noise_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: create_noise_buffer(device, width, height).as_entire_binding(),
+                resource: create_noise_buffer(device, self.seed, width, height).as_entire_binding(),
             }],
         })
     }
@@ -118,8 +126,8 @@ impl JfaComputeStarPass {
             label: Some("JFA compute pass"),
             timestamp_writes: Default::default(),
         });
-        let wg_x = (width + 7) / 16;
-        let wg_y = (height + 7) / 16;
+        let wg_x = width.div_ceil(16);
+        let wg_y = height.div_ceil(16);
         compute_pass.set_pipeline(&self.compute_pipeline);
         let p = width.max(height) as f32;
         for pass_i in 0..passes {
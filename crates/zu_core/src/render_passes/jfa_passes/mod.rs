@@ -15,10 +15,10 @@ use crate::{
         quad_vertex::QuadVertexRenderPass,
         seed_pass::SeedRenderPass,
     },
-    texture_manager::{TextureManager, textures::TextureType},
+    texture_manager::{TextureManager, dirty_rect::DirtyRect, textures::TextureType},
 };
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub enum JFAMode {
     Compute,
     ComputeStar,
@@ -26,10 +26,16 @@ pub enum JFAMode {
     Fragment,
 }
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub struct JfaRenderOptions {
     passes: u32,
     mode: JFAMode,
+    incremental_updates: bool,
+    /// Texels around painted areas recomputed while painting. The rest of the field is
+    /// settled with a full update once the scene stops changing.
+    max_influence: u32,
+    /// Fraction of the screen above which painted regions are recomputed in full.
+    full_update_threshold: f32,
 }
 
 impl Default for JfaRenderOptions {
@@ -37,10 +43,44 @@ impl Default for JfaRenderOptions {
         Self {
             passes: 9,
             mode: JFAMode::Compute,
+            incremental_updates: true,
+            max_influence: 128,
+            full_update_threshold: 0.5,
         }
     }
 }
 
+impl JfaRenderOptions {
+    pub fn supports_regions(&self) -> bool {
+        self.incremental_updates && self.mode == JFAMode::Compute
+    }
+
+    /// Expands painted rects into the regions that have to be recomputed. `None` means
+    /// a full update is cheaper.
+    pub fn update_regions(
+        &self,
+        dirty_rects: &[DirtyRect],
+        width: u32,
+        height: u32,
+    ) -> Option<Vec<DirtyRect>> {
+        if !self.supports_regions() || dirty_rects.is_empty() {
+            return None;
+        }
+        let mut regions: Vec<DirtyRect> = dirty_rects
+            .iter()
+            .map(|rect| rect.expanded(self.max_influence, width, height))
+            .collect();
+        DirtyRect::merge_overlapping(&mut regions);
+
+        let area: u64 = regions.iter().map(DirtyRect::area).sum();
+        if area as f64 > self.full_update_threshold as f64 * (width as f64 * height as f64) {
+            return None;
+        }
+
+        Some(regions)
+    }
+}
+
 pub struct JfaPassesManager {
     compute: JfaComputePass,
     compute_star: JfaComputeStarPass,
@@ -56,6 +96,7 @@ impl JfaPassesManager {
         height: u32,
         quad_render_pass: &QuadVertexRenderPass,
         texture_manager: &mut TextureManager,
+        seed: u32,
     ) -> Self {
         let _ = texture_manager.create_texture(
             "RadianceCascades",
@@ -82,7 +123,7 @@ impl JfaPassesManager {
         );
         Self {
             compute: JfaComputePass::new(device, texture_manager),
-            compute_star: JfaComputeStarPass::new(device, texture_manager, width, height),
+            compute_star: JfaComputeStarPass::new(device, texture_manager, seed, width, height),
             compute_one_shot: JfaComputeOneShotPass::new(device, texture_manager),
             fragment: JfaRenderPass::new(
                 device,
@@ -99,6 +140,10 @@ impl JfaPassesManager {
         self.compute_star.resize(device, width, height);
     }
 
+    pub fn set_seed(&mut self, device: &Device, seed: u32, width: u32, height: u32) {
+        self.compute_star.set_seed(device, seed, width, height);
+    }
+
     pub fn render(
         &mut self,
         options: &JfaRenderOptions,
@@ -108,16 +153,55 @@ impl JfaPassesManager {
         width: u32,
         height: u32,
     ) {
+        self.render_region(
+            options,
+            encoder,
+            texture_manager,
+            quad_render_pass,
+            width,
+            height,
+            DirtyRect::full(width, height),
+        );
+    }
+
+    /// Recomputes the JFA result inside `region` only. Modes other than `Compute` always
+    /// update the full texture.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_region(
+        &mut self,
+        options: &JfaRenderOptions,
+        encoder: &mut CommandEncoder,
+        texture_manager: &mut TextureManager,
+        quad_render_pass: &QuadVertexRenderPass,
+        width: u32,
+        height: u32,
+        region: DirtyRect,
+    ) {
+        let full = DirtyRect::full(width, height);
         match options.mode {
             JFAMode::Compute => {
                 self.seed_pass
-                    .render(encoder, texture_manager, quad_render_pass);
-                self.compute
-                    .render(encoder, texture_manager, options.passes, width, height);
+                    .render(encoder, texture_manager, quad_render_pass, region);
+                self.compute.render(
+                    encoder,
+                    texture_manager,
+                    options.passes,
+                    width,
+                    height,
+                    region,
+                );
+                // Keep both ping-pong textures identical, so the next region update
+                // reads final seeds outside of its region whichever texture it starts from.
+                let (result, other) = if options.passes.is_multiple_of(2) {
+                    ("JfaTexture", "JfaTexture1")
+                } else {
+                    ("JfaTexture1", "JfaTexture")
+                };
+                texture_manager.copy_region(encoder, result, other, region);
             }
             JFAMode::ComputeStar => {
                 self.seed_pass
-                    .render(encoder, texture_manager, quad_render_pass);
+                    .render(encoder, texture_manager, quad_render_pass, full);
                 self.compute_star
                     .render(encoder, texture_manager, options.passes, width, height);
             }
@@ -132,7 +216,7 @@ impl JfaPassesManager {
             }
             JFAMode::Fragment => {
                 self.seed_pass
-                    .render(encoder, texture_manager, quad_render_pass);
+                    .render(encoder, texture_manager, quad_render_pass, full);
                 self.fragment.multi_render(
                     encoder,
                     quad_render_pass,
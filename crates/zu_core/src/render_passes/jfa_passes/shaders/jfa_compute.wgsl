@@ -1,22 +1,29 @@
 @group(0) @binding(0) var input_texture: texture_2d<f32>;
 @group(1) @binding(0) var output_texture: texture_storage_2d<rgba32float, read_write>;
+@group(2) @binding(0) var scene_texture: texture_2d<f32>;
 
 struct PushConstants {
     one_over_size: vec2<f32>,
     texture_size: vec2<f32>,
     u_offset: i32,
     _pad: f32,
+    // Texels to update, max exclusive. Covers the whole texture on a full update.
+    region_min: vec2<i32>,
+    region_max: vec2<i32>,
 };
 
 var<push_constant> constants: PushConstants;
 
 @compute @workgroup_size(32, 32)
 fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
-    let pixelCoord = id.xy;
+    let pixelCoord = vec2<i32>(id.xy) + constants.region_min;
+    if any(pixelCoord >= constants.region_max) {
+        return;
+    }
 
-    let current_value = textureLoad(input_texture, vec2<i32>(pixelCoord), 0);
+    let current_value = textureLoad(input_texture, pixelCoord, 0);
     if current_value.z == 1.0 {
-        textureStore(output_texture, vec2<i32>(pixelCoord), current_value);
+        textureStore(output_texture, pixelCoord, current_value);
         return;
     }
 
@@ -30,14 +37,14 @@ fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
     for (var y: i32 = -1; y <= 1; y += 1) {
         for (var x: i32 = -1; x <= 1; x += 1) {
             let offset = vec2(x, y) * constants.u_offset;
-            let sampleCoord = vec2<i32>(pixelCoord) + offset;
+            let sampleCoord = pixelCoord + offset;
 
             let clampedCoord = clamp(sampleCoord, vec2<i32>(0), vec2<i32>(textureDim));
 
             let sample_value = textureLoad(input_texture, clampedCoord, 0);
             let sample_seed = sample_value.xy;
 
-            if sample_seed.x != 0.0 || sample_seed.y != 0.0 {
+            if (sample_seed.x != 0.0 || sample_seed.y != 0.0) && !isStale(sample_seed) {
                 let diff = sample_seed - uv;
                 let dist = dot(diff, diff);
                 if dist < nearestDist {
@@ -48,5 +55,12 @@ fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
         }
     }
 
-    textureStore(output_texture, vec2<i32>(pixelCoord), nearestSeed);
+    textureStore(output_texture, pixelCoord, nearestSeed);
+}
+
+// Texels outside the region keep the seeds of earlier updates, which may point at
+// surfaces erased since. The scene tells if the surface is still there.
+fn isStale(seed: vec2<f32>) -> bool {
+    let texel = vec2<i32>(seed * constants.texture_size);
+    return textureLoad(scene_texture, texel, 0).a <= 0.0;
 }
@@ -13,6 +13,9 @@ var<push_constant> constants: PushConstants;
 
 @compute @workgroup_size(16, 16)
 fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(output_texture)) {
+        return;
+    }
     let pixelCoord = id.xy;
 
     // UV текущего пикселя, нужен для расчета расстояния.
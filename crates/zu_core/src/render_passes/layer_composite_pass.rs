@@ -0,0 +1,154 @@
+use bytemuck::{Pod, Zeroable, bytes_of};
+use wgpu::{
+    BindGroupLayout, CommandEncoder, ComputePipelineDescriptor, Device, PushConstantRange,
+    ShaderStages,
+};
+
+use crate::{
+    layers::LayerStack,
+    texture_manager::{TextureManager, dirty_rect::DirtyRect, textures::EngineTexture},
+};
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+struct LayerCompositeConstants {
+    region_min: [i32; 2],
+    region_max: [i32; 2],
+    opacity: f32,
+    blend_mode: u32,
+    /// Non zero for the bottom layer, which starts from a cleared texel.
+    clear: u32,
+    transmission: f32,
+    reflective: u32,
+    fog: u32,
+    height: f32,
+    _padding: u32,
+}
+
+pub struct LayerCompositePass {
+    compute_pipeline: wgpu::ComputePipeline,
+    /// Fog and height, out of bind groups for one texture each.
+    outputs_layout: BindGroupLayout,
+}
+
+impl LayerCompositePass {
+    pub fn new(device: &Device, texture_manager: &TextureManager) -> Self {
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("./shaders/layer_composite.wgsl"));
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::ReadWrite,
+                format: wgpu::TextureFormat::Rgba32Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let outputs_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Layer composite outputs Bind Group Layout"),
+            entries: &[storage_entry(0), storage_entry(1)],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Layer composite pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                texture_manager.get_compute_bind_group_layout(),
+                texture_manager.get_compute_mut_bind_group_layout(),
+                &outputs_layout,
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<LayerCompositeConstants>() as u32,
+            }],
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Layer composite pass"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        LayerCompositePass {
+            compute_pipeline,
+            outputs_layout,
+        }
+    }
+
+    /// Blends the layers bottom to top into "SceneTexture", "MaterialTexture",
+    /// "FogTexture" and "HeightTexture" inside `region`.
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        texture_manager: &TextureManager,
+        layers: &LayerStack,
+        region: DirtyRect,
+    ) {
+        let (Some(scene), Some(material), Some(fog), Some(height)) = (
+            texture_manager.get_texture("SceneTexture"),
+            texture_manager.get_texture("MaterialTexture"),
+            texture_manager.get_texture("FogTexture"),
+            texture_manager.get_texture("HeightTexture"),
+        ) else {
+            return;
+        };
+        // Rebuilt every time, the textures are replaced when the canvas resizes.
+        let outputs = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Layer composite outputs Bind Group"),
+            layout: &self.outputs_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(fog.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(height.view()),
+                },
+            ],
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Layer composite pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, scene.compute_mut_group_f32(), &[]);
+        compute_pass.set_bind_group(2, material.compute_mut_group_f32(), &[]);
+        compute_pass.set_bind_group(3, &outputs, &[]);
+        for (i, layer) in layers.layers().iter().enumerate() {
+            // The bottom layer is always dispatched to clear the region, even when hidden.
+            if !layer.visible && i > 0 {
+                continue;
+            }
+            let Some(texture) = texture_manager.get_texture(&layer.texture_name()) else {
+                continue;
+            };
+            compute_pass.set_bind_group(1, texture.compute_bind_group(), &[]);
+            compute_pass.set_push_constants(
+                0,
+                bytes_of(&LayerCompositeConstants {
+                    region_min: [region.min_x as i32, region.min_y as i32],
+                    region_max: [region.max_x as i32, region.max_y as i32],
+                    opacity: if layer.visible { layer.opacity } else { 0.0 },
+                    blend_mode: layer.blend_mode as u32,
+                    clear: (i == 0) as u32,
+                    transmission: layer.transmission,
+                    reflective: layer.reflective as u32,
+                    fog: layer.fog as u32,
+                    height: layer.height,
+                    _padding: 0,
+                }),
+            );
+            compute_pass.dispatch_workgroups(
+                region.width().div_ceil(16),
+                region.height().div_ceil(16),
+                1,
+            );
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use bytemuck::{Pod, Zeroable, bytes_of};
+use glam::{IVec2, Mat4, Vec2, Vec4};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BufferUsages, CommandEncoder, Device, PushConstantRange,
+    Queue, Sampler, ShaderStages, TextureView,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::{
+    camera::Camera,
+    render_passes::quad_vertex::{QuadVertex, QuadVertexRenderPass},
+    texture_manager::{TextureManager, textures::EngineTexture},
+    world::TexelGrid,
+};
+
+/// Side length of the demo sprite.
+const DEMO_SPRITE_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LitSpriteId(usize);
+
+impl LitSpriteId {
+    /// Shaded ball loaded along with the pass, to try the lighting out.
+    pub const DEMO: LitSpriteId = LitSpriteId(0);
+}
+
+/// Sprite drawn over the lit canvas, shaded through its normal map by the light arriving
+/// around it. It does not cast shadows itself, pair it with a `SceneObject` for that.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LitSprite {
+    pub sprite: LitSpriteId,
+    pub position: Vec2,
+    /// Full width and height.
+    pub scale: Vec2,
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    /// Multiplies the albedo.
+    pub color: [f32; 4],
+    /// Scales the bumps, `0` shades the sprite like a flat card facing the viewer.
+    pub normal_strength: f32,
+}
+
+impl LitSprite {
+    pub fn new(sprite: LitSpriteId, position: Vec2, scale: Vec2) -> Self {
+        Self {
+            sprite,
+            position,
+            scale,
+            rotation: 0.0,
+            color: [1.0; 4],
+            normal_strength: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Instance {
+    position: Vec2,
+    scale: Vec2,
+    color: Vec4,
+    rotation: f32,
+    normal_strength: f32,
+    _padding: [f32; 2],
+}
+
+impl Instance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x4,
+        4 => Float32,
+        5 => Float32,
+    ];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LitSpriteConstants {
+    view_proj: Mat4,
+    /// World texel of the top left canvas texel.
+    canvas_origin: [f32; 2],
+    canvas_size: [f32; 2],
+    texels_per_unit: f32,
+    _padding: [f32; 3],
+}
+
+/// Draws normal mapped sprites over the shown radiance, lit by "RadianceCascades" and its
+/// first order harmonics.
+pub struct LitSpritePass {
+    render_pipeline: wgpu::RenderPipeline,
+    sprite_layout: BindGroupLayout,
+    /// The radiance and its harmonics, filtered between texels.
+    lighting_layout: BindGroupLayout,
+    sampler: Sampler,
+    /// Albedo and normal map of every loaded sprite.
+    sprites: Vec<BindGroup>,
+}
+
+impl LitSpritePass {
+    pub fn new(device: &Device, queue: &Queue, config: &wgpu::SurfaceConfiguration) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/lit_sprite.wgsl"));
+
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let sprite_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lit sprite Bind Group Layout"),
+            entries: &[sampler_entry, texture_entry(1), texture_entry(2)],
+        });
+        // Float32 textures are filterable with `FLOAT32_FILTERABLE`.
+        let lighting_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lit sprite lighting Bind Group Layout"),
+            entries: &[
+                sampler_entry,
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+            ],
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Lit sprite pipeline layout"),
+                bind_group_layouts: &[&sprite_layout, &lighting_layout],
+                push_constant_ranges: &[PushConstantRange {
+                    stages: ShaderStages::VERTEX_FRAGMENT,
+                    range: 0..std::mem::size_of::<LitSpriteConstants>() as u32,
+                }],
+            });
+
+        let blendable = config
+            .format
+            .guaranteed_format_features(device.features())
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE);
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Lit sprite pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[QuadVertex::desc(), Instance::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    // Float32 surfaces can not be blended without an extra feature, the
+                    // transparent texels are still discarded.
+                    blend: blendable.then_some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Negative scales flip the winding.
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Lit sprite sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut pass = LitSpritePass {
+            render_pipeline,
+            sprite_layout,
+            lighting_layout,
+            sampler,
+            sprites: Vec::new(),
+        };
+        let (albedo, normals) = demo_sprite();
+        pass.add_sprite(
+            device,
+            queue,
+            DEMO_SPRITE_SIZE,
+            DEMO_SPRITE_SIZE,
+            &albedo,
+            &normals,
+        );
+        pass
+    }
+
+    /// Uploads an RGBA8 albedo and its tangent space normal map, x right and y up, both
+    /// `width * height` texels row by row.
+    pub fn add_sprite(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        albedo: &[u8],
+        normals: &[u8],
+    ) -> LitSpriteId {
+        let upload = |label, format, data| {
+            device
+                .create_texture_with_data(
+                    queue,
+                    &wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d {
+                            width,
+                            height,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    },
+                    wgpu::util::TextureDataOrder::LayerMajor,
+                    data,
+                )
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let albedo = upload(
+            "Lit sprite albedo",
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            albedo,
+        );
+        // Normals are vectors, not colors.
+        let normals = upload(
+            "Lit sprite normals",
+            wgpu::TextureFormat::Rgba8Unorm,
+            normals,
+        );
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lit sprite Bind Group"),
+            layout: &self.sprite_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&albedo),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normals),
+                },
+            ],
+        });
+        self.sprites.push(bind_group);
+        LitSpriteId(self.sprites.len() - 1)
+    }
+
+    /// Draws `sprites` over `output_view`, seen through `camera`. `canvas_origin` is the
+    /// world texel of the top left canvas texel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        output_view: &TextureView,
+        texture_manager: &TextureManager,
+        quad_render_pass: &QuadVertexRenderPass,
+        camera: &Camera,
+        grid: TexelGrid,
+        canvas_origin: IVec2,
+        canvas_size: (u32, u32),
+        sprites: &[LitSprite],
+    ) {
+        puffin::profile_function!();
+        let (Some(radiance), Some(harmonics_x), Some(harmonics_y)) = (
+            texture_manager.get_texture("RadianceCascades"),
+            texture_manager.get_texture("RadianceHarmonicsX"),
+            texture_manager.get_texture("RadianceHarmonicsY"),
+        ) else {
+            return;
+        };
+        let sprite = |sprite: &LitSprite| sprite.sprite.0;
+        let mut sprites: Vec<LitSprite> = sprites
+            .iter()
+            .filter(|lit| sprite(lit) < self.sprites.len())
+            .copied()
+            .collect();
+        if sprites.is_empty() {
+            return;
+        }
+        // Sprites sharing textures are drawn with one call.
+        sprites.sort_by_key(sprite);
+        let instances: Vec<Instance> = sprites
+            .iter()
+            .map(|lit| Instance {
+                position: lit.position,
+                scale: lit.scale,
+                color: Vec4::from(lit.color),
+                rotation: lit.rotation,
+                normal_strength: lit.normal_strength,
+                _padding: [0.0; 2],
+            })
+            .collect();
+        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Lit sprite instances"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let lighting = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lit sprite lighting Bind Group"),
+            layout: &self.lighting_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(radiance.view()),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(harmonics_x.view()),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(harmonics_y.view()),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lit sprite pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                depth_slice: Default::default(),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(
+            ShaderStages::VERTEX_FRAGMENT,
+            0,
+            bytes_of(&LitSpriteConstants {
+                view_proj: camera.create_matrix(),
+                canvas_origin: canvas_origin.as_vec2().into(),
+                canvas_size: [canvas_size.0 as f32, canvas_size.1 as f32],
+                texels_per_unit: grid.texels_per_unit,
+                _padding: [0.0; 3],
+            }),
+        );
+        render_pass.set_bind_group(1, &lighting, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        let mut start = 0;
+        for group in sprites.chunk_by(|a, b| sprite(a) == sprite(b)) {
+            let end = start + group.len() as u32;
+            render_pass.set_bind_group(0, &self.sprites[sprite(&group[0])], &[]);
+            quad_render_pass.render_instances(&mut render_pass, start..end);
+            start = end;
+        }
+    }
+}
+
+/// Albedo and normals of `LitSpriteId::DEMO`, a white ball.
+fn demo_sprite() -> (Vec<u8>, Vec<u8>) {
+    let mut albedo = Vec::new();
+    let mut normals = Vec::new();
+    for y in 0..DEMO_SPRITE_SIZE {
+        for x in 0..DEMO_SPRITE_SIZE {
+            // Rows go down, the normal map y goes up.
+            let p = (Vec2::new(x as f32 + 0.5, DEMO_SPRITE_SIZE as f32 - y as f32 - 0.5)
+                / DEMO_SPRITE_SIZE as f32)
+                * 2.0
+                - 1.0;
+            let inside = p.length_squared() <= 1.0;
+            let z = (1.0 - p.length_squared()).max(0.0).sqrt();
+            let encode = |value: f32| ((value * 0.5 + 0.5) * 255.0).round() as u8;
+            albedo.extend_from_slice(&[255, 255, 255, if inside { 255 } else { 0 }]);
+            normals.extend_from_slice(&[encode(p.x), encode(p.y), encode(z), 255]);
+        }
+    }
+    (albedo, normals)
+}
@@ -1,5 +1,9 @@
+pub mod brush_pass;
 pub mod distant_field_pass;
+pub mod flood_fill_pass;
 pub mod jfa_passes;
+pub mod layer_composite_pass;
+pub mod lit_sprite_pass;
 pub mod object_render_pass;
 pub mod quad_vertex;
 pub mod radiance_cascades_passes;
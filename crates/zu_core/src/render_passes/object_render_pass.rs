@@ -1,268 +1,397 @@
-// use bytemuck::{Pod, Zeroable};
-// use glam::{Vec2, Vec4};
-// use wgpu::{
-//     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-//     BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferDescriptor, BufferUsages,
-//     CommandEncoder, Device, Queue, ShaderStages, TextureView, VertexBufferLayout,
-//     util::{BufferInitDescriptor, DeviceExt},
-// };
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec4};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferUsages, CommandEncoder, Device, Queue,
+    Sampler, ShaderStages,
+    util::{BufferInitDescriptor, DeviceExt},
+};
 
-// use crate::camera::{Camera, CameraUniform};
+use crate::{
+    camera::Camera,
+    layers::FULL_HEIGHT,
+    render_passes::quad_vertex::{QuadVertex, QuadVertexRenderPass},
+    texture_manager::{TextureManager, textures::EngineTexture},
+};
 
-// #[repr(C)]
-// #[derive(Clone, Copy, Pod, Zeroable)]
-// struct QuadVertex {
-//     position: Vec2,
-// }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteId(usize);
 
-// impl QuadVertex {
-//     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-//         use std::mem;
-//         wgpu::VertexBufferLayout {
-//             array_stride: mem::size_of::<QuadVertex>() as wgpu::BufferAddress,
-//             step_mode: wgpu::VertexStepMode::Vertex, // per-vertex
-//             attributes: &[wgpu::VertexAttribute {
-//                 offset: 0,
-//                 shader_location: 0, // matches @location(0)
-//                 format: wgpu::VertexFormat::Float32x2,
-//             }],
-//         }
-//     }
-// }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectShape {
+    Quad,
+    /// Circle inscribed in the quad.
+    Circle,
+    /// Quad textured with the sprite, multiplied by the object color.
+    Sprite(SpriteId),
+}
 
-// // a unit quad (two triangles)
-// const QUAD_VERTICES: &[QuadVertex] = &[
-//     QuadVertex {
-//         position: Vec2::new(-0.5, -0.5),
-//     },
-//     QuadVertex {
-//         position: Vec2::new(0.5, -0.5),
-//     },
-//     QuadVertex {
-//         position: Vec2::new(0.5, 0.5),
-//     },
-//     QuadVertex {
-//         position: Vec2::new(-0.5, 0.5),
-//     },
-// ];
+impl ObjectShape {
+    fn id(&self) -> u32 {
+        match self {
+            ObjectShape::Quad => 0,
+            ObjectShape::Circle => 1,
+            ObjectShape::Sprite(_) => 2,
+        }
+    }
+}
 
-// const QUAD_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+/// Object drawn into the scene every frame, in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneObject {
+    pub shape: ObjectShape,
+    pub position: Vec2,
+    /// Full width and height.
+    pub scale: Vec2,
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    pub color: [f32; 4],
+    /// Scale of the color as emitted light, `0` only casts shadows.
+    pub emission: f32,
+    /// Fraction of the light passing through, tinted by the color.
+    pub transmission: f32,
+    /// Mirrors the light hitting it, tinted by the color, instead of glowing.
+    pub reflective: bool,
+    /// How far the object reaches up, in world units, `FULL_HEIGHT` by default. Only
+    /// analytic lights shine over it.
+    pub height: f32,
+}
 
-// #[repr(C)]
-// #[derive(Clone, Copy, Pod, Zeroable)]
-// struct Instance {
-//     position: Vec2,
-//     scale: Vec2,
-//     color: Vec4,
-// }
+impl SceneObject {
+    pub fn new(shape: ObjectShape, position: Vec2, scale: Vec2, color: [f32; 4]) -> Self {
+        Self {
+            shape,
+            position,
+            scale,
+            rotation: 0.0,
+            color,
+            emission: 0.0,
+            transmission: 0.0,
+            reflective: false,
+            height: FULL_HEIGHT,
+        }
+    }
 
-// impl Instance {
-//     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-//         use std::mem;
-//         wgpu::VertexBufferLayout {
-//             array_stride: mem::size_of::<Instance>() as wgpu::BufferAddress,
-//             step_mode: wgpu::VertexStepMode::Instance, // per-instance
-//             attributes: &[
-//                 // @location(1) position
-//                 wgpu::VertexAttribute {
-//                     offset: 0,
-//                     shader_location: 1,
-//                     format: wgpu::VertexFormat::Float32x2,
-//                 },
-//                 // @location(2) scale
-//                 wgpu::VertexAttribute {
-//                     offset: mem::size_of::<Vec2>() as wgpu::BufferAddress,
-//                     shader_location: 2,
-//                     format: wgpu::VertexFormat::Float32x2,
-//                 },
-//                 // @location(3) color
-//                 wgpu::VertexAttribute {
-//                     offset: (mem::size_of::<Vec4>()) as wgpu::BufferAddress,
-//                     shader_location: 3,
-//                     format: wgpu::VertexFormat::Float32x4,
-//                 },
-//             ],
-//         }
-//     }
-// }
+    /// World space bounding box, `(min, max)`.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let half = self.scale.abs() * 0.5;
+        let (sin, cos) = self.rotation.sin_cos();
+        let extent = Vec2::new(
+            cos.abs() * half.x + sin.abs() * half.y,
+            sin.abs() * half.x + cos.abs() * half.y,
+        );
+        (self.position - extent, self.position + extent)
+    }
+}
 
-// pub struct ObjectRenderPass {
-//     render_pipeline: wgpu::RenderPipeline,
-//     camera_bind_group: BindGroup,
-//     camera_buffer: Buffer,
-//     vertex_buffer: Buffer,
-//     index_buffer: Buffer,
-//     index_count: u32,
-// }
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Instance {
+    position: Vec2,
+    scale: Vec2,
+    color: Vec4,
+    rotation: f32,
+    shape: u32,
+    emission: f32,
+    transmission: f32,
+    reflectivity: f32,
+    height: f32,
+    _padding: [f32; 2],
+}
 
-// impl ObjectRenderPass {
-//     pub fn new(device: &Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) -> Self {
-//         let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/obj_draw.wgsl"));
+impl Instance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 9] = wgpu::vertex_attr_array![
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x4,
+        4 => Float32,
+        5 => Uint32,
+        6 => Float32,
+        7 => Float32,
+        8 => Float32,
+        9 => Float32,
+    ];
 
-//         let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
-//             label: Some(&"Camera"),
-//             contents: bytemuck::bytes_of(&camera.get_camera_uninform()),
-//             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-//         });
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
 
-//         let camera_bind_group_layout =
-//             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-//                 label: Some("Camera"),
-//                 entries: &[BindGroupLayoutEntry {
-//                     binding: 0,
-//                     visibility: ShaderStages::VERTEX,
-//                     ty: BindingType::Buffer {
-//                         ty: wgpu::BufferBindingType::Uniform,
-//                         has_dynamic_offset: false,
-//                         min_binding_size: None,
-//                     },
-//                     count: None,
-//                 }],
-//             });
+/// Instanced renderer of quads, circles and sprites.
+pub struct ObjectRenderPass {
+    render_pipeline: wgpu::RenderPipeline,
+    camera_bind_group: BindGroup,
+    camera_buffer: Buffer,
+    sampler: Sampler,
+    /// Bind groups of the loaded sprites, the first one is a white texel used by the
+    /// untextured shapes.
+    sprites: Vec<BindGroup>,
+}
 
-//         let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
-//             label: Some("Camera"),
-//             layout: &camera_bind_group_layout,
-//             entries: &[BindGroupEntry {
-//                 binding: 0,
-//                 resource: camera_buffer.as_entire_binding(),
-//             }],
-//         });
+impl ObjectRenderPass {
+    pub fn new(device: &Device, queue: &Queue, texture_manager: &TextureManager) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/obj_draw.wgsl"));
 
-//         let render_pipeline_layout =
-//             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-//                 label: Some("Render Pipeline Layout"),
-//                 bind_group_layouts: &[&camera_bind_group_layout],
-//                 push_constant_ranges: &[],
-//             });
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera"),
+            contents: bytemuck::bytes_of(&Camera::default().get_camera_uninform()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
 
-//         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-//             label: Some("Render Pipeline"),
-//             layout: Some(&render_pipeline_layout),
-//             vertex: wgpu::VertexState {
-//                 module: &shader,
-//                 entry_point: Some("vs_main"),                     // 1.
-//                 buffers: &[QuadVertex::desc(), Instance::desc()], // 2.
-//                 compilation_options: wgpu::PipelineCompilationOptions::default(),
-//             },
-//             fragment: Some(wgpu::FragmentState {
-//                 // 3.
-//                 module: &shader,
-//                 entry_point: Some("fs_main"),
-//                 targets: &[Some(wgpu::ColorTargetState {
-//                     // 4.
-//                     format: config.format,
-//                     blend: Some(wgpu::BlendState::REPLACE),
-//                     write_mask: wgpu::ColorWrites::ALL,
-//                 })],
-//                 compilation_options: wgpu::PipelineCompilationOptions::default(),
-//             }),
-//             primitive: wgpu::PrimitiveState {
-//                 topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-//                 strip_index_format: None,
-//                 front_face: wgpu::FrontFace::Ccw, // 2.
-//                 cull_mode: Some(wgpu::Face::Back),
-//                 // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-//                 polygon_mode: wgpu::PolygonMode::Fill,
-//                 // Requires Features::DEPTH_CLIP_CONTROL
-//                 unclipped_depth: false,
-//                 // Requires Features::CONSERVATIVE_RASTERIZATION
-//                 conservative: false,
-//             },
-//             depth_stencil: None, // 1.
-//             multisample: wgpu::MultisampleState {
-//                 count: 1,                         // 2.
-//                 mask: !0,                         // 3.
-//                 alpha_to_coverage_enabled: false, // 4.
-//             },
-//             multiview: None, // 5.
-//             cache: None,     // 6.
-//         });
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Camera"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
 
-//         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-//             label: Some("Quad Vertex Buffer"),
-//             contents: bytemuck::cast_slice(QUAD_VERTICES),
-//             usage: wgpu::BufferUsages::VERTEX,
-//         });
-//         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-//             label: Some("Quad Index Buffer"),
-//             contents: bytemuck::cast_slice(QUAD_INDICES),
-//             usage: wgpu::BufferUsages::INDEX,
-//         });
-//         let index_count = QUAD_INDICES.len() as u32;
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Camera"),
+            layout: &camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
 
-//         ObjectRenderPass {
-//             render_pipeline,
-//             camera_bind_group,
-//             index_buffer,
-//             index_count,
-//             vertex_buffer,
-//             camera_buffer,
-//         }
-//     }
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Object render pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    texture_manager.get_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
 
-//     pub fn render(
-//         &mut self,
-//         encoder: &mut CommandEncoder,
-//         device: &Device,
-//         queue: &Queue,
-//         view: &TextureView,
-//         camera: &Camera,
-//     ) {
-//         let instances = vec![
-//             Instance {
-//                 position: Vec2::ZERO,
-//                 scale: Vec2::new(1.0, 1.0),
-//                 color: Vec4::new(1.0, 1.0, 0.0, 1.0),
-//             },
-//             Instance {
-//                 position: Vec2::new(0.0, 1.0),
-//                 scale: Vec2::new(1.0, 1.0),
-//                 color: Vec4::new(1.0, 0.0, 1.0, 1.0),
-//             },
-//         ];
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Object render pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[QuadVertex::desc(), Instance::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        // Float32 targets can not be blended without an extra feature.
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Negative scales flip the winding.
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
 
-//         // Per frame: upload instance buffer
-//         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-//             label: Some("Instance Buffer"),
-//             contents: bytemuck::cast_slice(&instances), // Vec<Instance>
-//             usage: wgpu::BufferUsages::VERTEX,
-//         });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
 
-//         queue.write_buffer(
-//             &self.camera_buffer,
-//             0,
-//             bytemuck::bytes_of(&camera.get_camera_uninform()),
-//         );
+        let mut pass = ObjectRenderPass {
+            render_pipeline,
+            camera_bind_group,
+            camera_buffer,
+            sampler,
+            sprites: Vec::new(),
+        };
+        pass.add_sprite(device, queue, texture_manager, 1, 1, &[255; 4]);
+        pass
+    }
 
-//         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-//             label: Some("Render Pass"),
-//             color_attachments: &[
-//                 // This is what @location(0) in the fragment shader targets
-//                 Some(wgpu::RenderPassColorAttachment {
-//                     view,
-//                     resolve_target: None,
-//                     ops: wgpu::Operations {
-//                         load: wgpu::LoadOp::Clear(wgpu::Color {
-//                             r: 0.1,
-//                             g: 0.2,
-//                             b: 0.3,
-//                             a: 1.0,
-//                         }),
-//                         store: wgpu::StoreOp::Store,
-//                     },
-//                 }),
-//             ],
-//             depth_stencil_attachment: None,
-//             timestamp_writes: Default::default(),
-//             occlusion_query_set: Default::default(),
-//         });
+    /// Uploads an RGBA8 sprite, `rgba` holds `width * height` texels row by row.
+    pub fn add_sprite(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &TextureManager,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> SpriteId {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Sprite"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            rgba,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Sprite Bind Group"),
+            layout: texture_manager.get_bind_group_layout(),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+        });
+        self.sprites.push(bind_group);
+        SpriteId(self.sprites.len() - 1)
+    }
 
-//         render_pass.set_pipeline(&self.render_pipeline); // 2.
-//         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-//         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-//         render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-//         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-//         render_pass.draw_indexed(0..self.index_count, 0, 0..instances.len() as u32);
-//     }
-// }
+    /// Draws `objects` over "SceneTexture", "MaterialTexture" and "HeightTexture", `camera`
+    /// maps the world onto the textures.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &TextureManager,
+        quad_render_pass: &QuadVertexRenderPass,
+        camera: &Camera,
+        objects: &[SceneObject],
+    ) {
+        puffin::profile_function!();
+        let (Some(scene), Some(material), Some(height)) = (
+            texture_manager.get_texture("SceneTexture"),
+            texture_manager.get_texture("MaterialTexture"),
+            texture_manager.get_texture("HeightTexture"),
+        ) else {
+            return;
+        };
+        if objects.is_empty() {
+            return;
+        }
+        // Objects sharing a sprite are drawn with one call.
+        let sprite = |object: &SceneObject| match object.shape {
+            ObjectShape::Sprite(SpriteId(index)) if index < self.sprites.len() => index,
+            _ => 0,
+        };
+        let mut objects = objects.to_vec();
+        objects.sort_by_key(sprite);
+        let instances: Vec<Instance> = objects
+            .iter()
+            .map(|object| Instance {
+                position: object.position,
+                scale: object.scale,
+                color: Vec4::from(object.color),
+                rotation: object.rotation,
+                shape: object.shape.id(),
+                emission: object.emission,
+                transmission: object.transmission,
+                reflectivity: object.reflective as u32 as f32,
+                height: object.height,
+                _padding: [0.0; 2],
+            })
+            .collect();
+
+        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: BufferUsages::VERTEX,
+        });
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::bytes_of(&camera.get_camera_uninform()),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Object render pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: scene.view(),
+                    resolve_target: None,
+                    depth_slice: Default::default(),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+                Some(wgpu::RenderPassColorAttachment {
+                    view: material.view(),
+                    resolve_target: None,
+                    depth_slice: Default::default(),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+                Some(wgpu::RenderPassColorAttachment {
+                    view: height.view(),
+                    resolve_target: None,
+                    depth_slice: Default::default(),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        let mut start = 0;
+        for group in objects.chunk_by(|a, b| sprite(a) == sprite(b)) {
+            let end = start + group.len() as u32;
+            render_pass.set_bind_group(1, &self.sprites[sprite(&group[0])], &[]);
+            quad_render_pass.render_instances(&mut render_pass, start..end);
+            start = end;
+        }
+    }
+}
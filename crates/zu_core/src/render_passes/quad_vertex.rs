@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use bytemuck::{Pod, Zeroable};
 use glam::Vec2;
 use wgpu::{Buffer, Device, RenderPass, ShaderModule, util::DeviceExt};
@@ -69,9 +71,14 @@ impl QuadVertexRenderPass {
     }
 
     pub fn render(&self, render_pass: &mut RenderPass) {
+        self.render_instances(render_pass, 0..1);
+    }
+
+    /// Draws the quad once per instance, the instance buffer goes in slot 1.
+    pub fn render_instances(&self, render_pass: &mut RenderPass, instances: Range<u32>) {
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..(QUAD_INDICES.len() as u32), 0, 0..1);
+        render_pass.draw_indexed(0..(QUAD_INDICES.len() as u32), 0, instances);
     }
 }
 
@@ -1,13 +1,15 @@
 use egui_probe::EguiProbe;
-use wgpu::{CommandEncoder, Device};
+use wgpu::{CommandEncoder, Device, Queue};
 
 use crate::{
+    lights::LightData,
     render_passes::{
         quad_vertex::QuadVertexRenderPass,
         radiance_cascades_passes::{
             radiance_render::RadianceRenderPass,
             radiance_render_compute::RadianceRenderComputePass,
             radiance_render_old_pass::RadianceRenderOLDPass,
+            reference::{ReferenceAccumulatePass, ReferenceOptions},
         },
     },
     texture_manager::{TextureManager, textures::TextureType},
@@ -16,15 +18,18 @@ use crate::{
 pub mod radiance_render;
 pub mod radiance_render_compute;
 pub mod radiance_render_old_pass;
+pub mod ray_sampling;
+pub mod reference;
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub enum RadianceMode {
     Fragment(radiance_render::RadiansOptions),
     Compute(radiance_render_compute::RadiansOptions),
     FragmentOLD(radiance_render_old_pass::RadiansOptionsOLD),
+    Reference(ReferenceOptions),
 }
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub struct RadianceCascadesRenderOptions {
     radiance_mode: RadianceMode,
 }
@@ -37,21 +42,49 @@ impl Default for RadianceCascadesRenderOptions {
     }
 }
 
+impl RadianceCascadesRenderOptions {
+    /// Whether the output feeds back into the next frame, so it keeps changing for a
+    /// few frames after the inputs stop. `fog` tells if there is any fog to scatter light.
+    pub fn feeds_back(&self, fog: bool) -> bool {
+        match self.radiance_mode {
+            RadianceMode::Compute(options) => options.feeds_back(fog),
+            _ => false,
+        }
+    }
+
+    /// Options of the reference mode, if it is the one selected.
+    pub fn reference(&self) -> Option<ReferenceOptions> {
+        match self.radiance_mode {
+            RadianceMode::Reference(options) => Some(options),
+            _ => None,
+        }
+    }
+}
+
 pub struct RadianceCascadesPassesManager {
     old_pass: RadianceRenderOLDPass,
     pass: RadianceRenderPass,
     compute: RadianceRenderComputePass,
+    reference: ReferenceAccumulatePass,
+    /// Options the compute mode last rendered with, the reference traces the same scene.
+    compute_options: radiance_render_compute::RadiansOptions,
+    /// The last render wrote "RadianceHarmonicsX/Y", only the compute and reference
+    /// modes do.
+    harmonics: bool,
     width: u32,
     height: u32,
+    seed: u32,
 }
 
 impl RadianceCascadesPassesManager {
     pub fn new(
         device: &Device,
+        queue: &Queue,
         width: u32,
         height: u32,
         quad_render_pass: &QuadVertexRenderPass,
         texture_manager: &mut TextureManager,
+        seed: u32,
     ) -> Self {
         let _ = texture_manager.create_texture(
             "RadianceCascades",
@@ -60,16 +93,42 @@ impl RadianceCascadesPassesManager {
             TextureType::Standard,
             1.0,
         );
+        // First order circular harmonics of the radiance, the average of radiance times
+        // ray direction in canvas texels (y down) per color channel. Only written by the
+        // compute mode.
+        for name in ["RadianceHarmonicsX", "RadianceHarmonicsY"] {
+            texture_manager.create_texture(
+                name,
+                (width, height),
+                device,
+                TextureType::Standard,
+                1.0,
+            );
+        }
+        // Running sum of the reference mode frames.
+        texture_manager.create_texture(
+            "RadianceReferenceSum",
+            (width, height),
+            device,
+            TextureType::Standard,
+            1.0,
+        );
 
         let old_pass = RadianceRenderOLDPass::new(device, quad_render_pass, texture_manager);
         let pass = RadianceRenderPass::new(device, quad_render_pass, texture_manager);
-        let compute = RadianceRenderComputePass::new(device, texture_manager);
+        let mut compute = RadianceRenderComputePass::new(device, queue, texture_manager);
+        compute.set_seed(seed);
+        let reference = ReferenceAccumulatePass::new(device, texture_manager);
         Self {
             old_pass,
             pass,
             compute,
+            reference,
+            compute_options: Default::default(),
+            harmonics: false,
             width,
             height,
+            seed,
         }
     }
 
@@ -78,26 +137,99 @@ impl RadianceCascadesPassesManager {
         self.height = height
     }
 
+    /// Seeds the ray noise of every mode but the old one.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.compute.set_seed(seed);
+    }
+
+    /// Frames averaged by the reference mode so far.
+    pub fn reference_samples(&self) -> u32 {
+        self.reference.samples()
+    }
+
+    /// Starts the reference mode average over, the inputs changed.
+    pub fn restart_reference(&mut self) {
+        self.reference.restart();
+    }
+
+    /// Whether the reference mode is selected and still has frames to average.
+    pub fn converging(&self, render_options: &RadianceCascadesRenderOptions) -> bool {
+        render_options
+            .reference()
+            .is_some_and(|options| !options.converged(self.reference.samples()))
+    }
+
+    /// Whether "RadianceHarmonicsX/Y" belong to the radiance last rendered.
+    pub fn has_harmonics(&self) -> bool {
+        self.harmonics
+    }
+
+    /// Only used by the compute and reference modes.
+    pub fn set_environment_ring(&mut self, queue: &Queue, colors: &[[f32; 4]]) {
+        self.compute.set_environment_ring(queue, colors);
+    }
+
+    /// `lights` and `fog` are only used by the compute and reference modes.
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &mut self,
         render_options: &RadianceCascadesRenderOptions,
         encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
         texture_manager: &mut TextureManager,
         quad_render_pass: &QuadVertexRenderPass,
+        lights: &[LightData],
+        fog: bool,
     ) {
+        self.harmonics = matches!(
+            render_options.radiance_mode,
+            RadianceMode::Compute(_) | RadianceMode::Reference(_)
+        );
         match render_options.radiance_mode {
             RadianceMode::Fragment(options) => {
-                self.pass
-                    .render(encoder, options, texture_manager, quad_render_pass);
+                self.pass.render(
+                    encoder,
+                    options,
+                    self.seed,
+                    texture_manager,
+                    quad_render_pass,
+                );
             }
             RadianceMode::Compute(options) => {
-                self.compute
-                    .render(encoder, texture_manager, options, self.width, self.height);
+                self.compute_options = options;
+                self.compute.render(
+                    encoder,
+                    device,
+                    queue,
+                    texture_manager,
+                    options,
+                    lights,
+                    fog,
+                    self.width,
+                    self.height,
+                );
             }
             RadianceMode::FragmentOLD(options) => {
                 self.old_pass
                     .render(encoder, options, texture_manager, quad_render_pass);
             }
+            RadianceMode::Reference(options) => {
+                self.compute.render(
+                    encoder,
+                    device,
+                    queue,
+                    texture_manager,
+                    options.trace_options(self.compute_options),
+                    lights,
+                    fog,
+                    self.width,
+                    self.height,
+                );
+                self.reference
+                    .render(encoder, texture_manager, self.width, self.height);
+            }
         }
     }
 }
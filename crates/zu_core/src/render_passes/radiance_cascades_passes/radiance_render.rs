@@ -19,6 +19,7 @@ struct RadianceCascadesConstants {
     max_steps: i32,
     enable_noise: i32,
     show_grain: i32,
+    seed: u32,
 }
 
 pub struct RadianceRenderPass {
@@ -92,6 +93,7 @@ impl RadianceRenderPass {
         &mut self,
         encoder: &mut CommandEncoder,
         options: RadiansOptions,
+        seed: u32,
         texture_manager: &TextureManager,
         quad_render_pass: &QuadVertexRenderPass,
     ) {
@@ -132,6 +134,7 @@ impl RadianceRenderPass {
                 max_steps: options.max_steps as i32,
                 enable_noise: options.enable_noise as i32,
                 show_grain: options.show_grain as i32,
+                seed,
             }),
         );
         render_pass.set_bind_group(
@@ -154,7 +157,7 @@ impl RadianceRenderPass {
     }
 }
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub struct RadiansOptions {
     ray_count: u32,
     accum_radiance: bool,
@@ -1,12 +1,18 @@
 use bytemuck::{Pod, Zeroable, bytes_of};
 use egui_probe::EguiProbe;
 use wgpu::{
-    CommandEncoder, ComputePipelineDescriptor, Device,
-    PushConstantRange, ShaderStages,
-    util::RenderEncoder,
+    BindGroupLayout, Buffer, CommandEncoder, ComputePipelineDescriptor, Device, PushConstantRange,
+    Queue, ShaderStages, Texture, TextureView,
+    util::{BufferInitDescriptor, DeviceExt, RenderEncoder},
 };
 
-use crate::texture_manager::{TextureManager, textures::EngineTexture};
+use crate::{
+    lights::LightData,
+    render_passes::radiance_cascades_passes::ray_sampling::{
+        BLUE_NOISE_SIZE, RaySampling, blue_noise,
+    },
+    texture_manager::{TextureManager, textures::EngineTexture},
+};
 
 #[repr(C)]
 #[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
@@ -16,27 +22,209 @@ struct RadianceCascadesConstants {
     pub max_steps: i32,
     pub enable_noise: i32,
     pub show_grain: i32,
-    pub _padding0: i32,
+    pub light_count: u32,
     pub resolution: [f32; 2],
-    pub _padding1: [f32; 2], // чтобы размер был кратен 16 байт
+    pub boundary_mode: u32,
+    pub sky_intensity: f32,
+    pub shadow_mode: u32,
+    pub shadow_samples: u32,
+    pub ambient: [f32; 4],
+    pub sky_color: [f32; 4],
+    pub ground_color: [f32; 4],
+    pub bounce_strength: f32,
+    pub max_reflections: u32,
+    pub fog_density: f32,
+    pub fog_scattering: f32,
+    pub frame_index: u32,
+    pub sampling: u32,
+    pub seed: u32,
+    pub _padding3: u32,
+}
+
+/// Entries of the environment ring, spread evenly over the ray angles.
+pub const ENVIRONMENT_RING_SIZE: usize = 256;
+
+/// What a ray sees once it leaves the canvas.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EguiProbe)]
+pub enum BoundaryMode {
+    Black = 0,
+    /// The same ambient color in every direction.
+    Ambient = 1,
+    /// Blends from the ground color for rays going down to the sky color going up.
+    SkyGradient = 2,
+    /// The environment ring, looked up by ray angle.
+    SkyRing = 3,
+    /// The canvas edges are surfaces, colored like the scene texels along them. They also
+    /// block sun lights.
+    Walls = 4,
+}
+
+/// How the analytic lights cast shadows.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EguiProbe)]
+pub enum ShadowMode {
+    /// Every light shines from a single point, shadows are sharp.
+    Hard = 0,
+    /// One march through the distance field per light, dimmed by how close surfaces come
+    /// to the cone towards the emitter. Cheap, but the penumbra only grows outwards from
+    /// the sharp shadow.
+    Cone = 1,
+    /// Averages `shadow_samples` marches to points spread over the emitter, the reference
+    /// for `Cone`.
+    Sampled = 2,
 }
 
 pub struct RadianceRenderComputePass {
     compute_pipeline: wgpu::ComputePipeline,
+    inputs_layout: BindGroupLayout,
+    lights_buffer: Buffer,
+    environment_ring: Buffer,
+    /// Copy of the last output, read back by the bounce lighting.
+    previous_radiance: Texture,
+    blue_noise: TextureView,
+    /// Renders since the seed was set, moves the ray jitter along.
+    frame_index: u32,
+    seed: u32,
 }
 
 impl RadianceRenderComputePass {
-    pub fn new(device: &Device, texture_manager: &mut TextureManager) -> Self {
+    pub fn new(device: &Device, queue: &Queue, texture_manager: &mut TextureManager) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!(
             "./shaders/radiance_cascades_compute.wgsl"
         ));
 
+        let inputs_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Radiance Inputs Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let environment_ring = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Environment ring"),
+            contents: bytemuck::cast_slice(&default_environment_ring()),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let lights_buffer = create_lights_buffer(device, 16);
+        let previous_radiance = create_previous_radiance(device, 1, 1);
+        let blue_noise = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("Blue noise"),
+                    size: wgpu::Extent3d {
+                        width: BLUE_NOISE_SIZE,
+                        height: BLUE_NOISE_SIZE,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::R8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                wgpu::util::TextureDataOrder::LayerMajor,
+                blue_noise(),
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Radiance compute pass layout descriptor"),
             bind_group_layouts: &[
                 texture_manager.get_compute_bind_group_layout(),
                 texture_manager.get_compute_bind_group_layout(),
                 texture_manager.get_compute_mut_bind_group_layout(),
+                &inputs_layout,
             ],
             push_constant_ranges: &[PushConstantRange {
                 stages: ShaderStages::COMPUTE,
@@ -52,17 +240,126 @@ impl RadianceRenderComputePass {
             compilation_options: Default::default(),
             cache: Default::default(),
         });
-        RadianceRenderComputePass { compute_pipeline }
+        RadianceRenderComputePass {
+            compute_pipeline,
+            inputs_layout,
+            lights_buffer,
+            environment_ring,
+            previous_radiance,
+            blue_noise,
+            frame_index: 0,
+            seed: 0,
+        }
     }
 
+    /// Picks the noise pattern of `seed` and starts its frame sequence over.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.frame_index = 0;
+    }
+
+    /// Replaces the environment ring with `colors`, going counterclockwise from the
+    /// direction of +x. Any number of colors is stretched over the whole ring.
+    pub fn set_environment_ring(&mut self, queue: &Queue, colors: &[[f32; 4]]) {
+        if colors.is_empty() {
+            return;
+        }
+        let ring: Vec<[f32; 4]> = (0..ENVIRONMENT_RING_SIZE)
+            .map(|i| colors[i * colors.len() / ENVIRONMENT_RING_SIZE])
+            .collect();
+        queue.write_buffer(&self.environment_ring, 0, bytemuck::cast_slice(&ring));
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &mut self,
         encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
         texture_manager: &TextureManager,
         options: RadiansOptions,
+        lights: &[LightData],
+        fog: bool,
         width: u32,
         height: u32,
     ) {
+        let size = std::mem::size_of_val(lights) as u64;
+        if size > self.lights_buffer.size() {
+            self.lights_buffer = create_lights_buffer(device, lights.len().next_power_of_two());
+        }
+        let previous = self.previous_radiance.size();
+        let resized = previous.width != width || previous.height != height;
+        if resized {
+            self.previous_radiance = create_previous_radiance(device, width, height);
+        }
+        // Rebuilt every time, the composited textures are replaced when the canvas resizes.
+        let material = texture_manager.get_texture("MaterialTexture").unwrap();
+        let fog_texture = texture_manager.get_texture("FogTexture").unwrap();
+        let harmonics_x = texture_manager.get_texture("RadianceHarmonicsX").unwrap();
+        let harmonics_y = texture_manager.get_texture("RadianceHarmonicsY").unwrap();
+        let height_field = texture_manager.get_texture("HeightTexture").unwrap();
+        let previous_view = self
+            .previous_radiance
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let inputs_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Radiance Inputs Bind Group"),
+            layout: &self.inputs_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.environment_ring.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&previous_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(material.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(fog_texture.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&self.blue_noise),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(harmonics_x.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(harmonics_y.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(height_field.view()),
+                },
+            ],
+        });
+        // A freshly created history holds nothing to bounce yet.
+        let bounce_strength = if resized {
+            0.0
+        } else {
+            options.bounce_strength
+        };
+        let output = texture_manager.get_texture("RadianceCascades").unwrap();
+        if options.feeds_back(fog) && !resized {
+            encoder.copy_texture_to_texture(
+                output.texture().as_image_copy(),
+                self.previous_radiance.as_image_copy(),
+                self.previous_radiance.size(),
+            );
+        }
+        if !lights.is_empty() {
+            queue.write_buffer(&self.lights_buffer, 0, bytemuck::cast_slice(lights));
+        }
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Radiance compute pass"),
             timestamp_writes: Default::default(),
@@ -76,9 +373,23 @@ impl RadianceRenderComputePass {
                 max_steps: options.max_steps as i32,
                 enable_noise: options.enable_noise as i32,
                 show_grain: options.show_grain as i32,
-                _padding0: 0,
+                light_count: lights.len() as u32,
                 resolution: [width as f32, height as f32],
-                _padding1: [0.0, 0.0],
+                boundary_mode: options.boundary as u32,
+                sky_intensity: options.sky_intensity,
+                shadow_mode: options.shadows as u32,
+                shadow_samples: options.shadow_samples,
+                ambient: options.ambient.to_array(),
+                sky_color: options.sky_color.to_array(),
+                ground_color: options.ground_color.to_array(),
+                bounce_strength,
+                max_reflections: options.max_reflections,
+                fog_density: if fog { options.fog_density } else { 0.0 },
+                fog_scattering: options.fog_scattering,
+                frame_index: self.frame_index,
+                sampling: options.sampling as u32,
+                seed: self.seed,
+                _padding3: 0,
             }),
         );
         compute_pass.set_bind_group(
@@ -97,27 +408,105 @@ impl RadianceRenderComputePass {
                 .compute_bind_group(),
             &[],
         );
-        compute_pass.set_bind_group(
-            2,
-            texture_manager
-                .get_texture("RadianceCascades")
-                .unwrap()
-                .compute_mut_group_f32(),
-            &[],
-        );
-        let wg_x = (width + 7) / 16;
-        let wg_y = (height + 7) / 16;
+        compute_pass.set_bind_group(2, output.compute_mut_group_f32(), &[]);
+        compute_pass.set_bind_group(3, &inputs_bind_group, &[]);
+        let wg_x = width.div_ceil(16);
+        let wg_y = height.div_ceil(16);
         compute_pass.dispatch_workgroups(wg_x, wg_y, 1);
+        self.frame_index = self.frame_index.wrapping_add(1);
     }
 }
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+fn create_lights_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Lights"),
+        size: (capacity * std::mem::size_of::<LightData>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_previous_radiance(device: &Device, width: u32, height: u32) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Previous radiance"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+/// Dim blue all around, warming up towards a bright horizon on the +x side.
+fn default_environment_ring() -> Vec<[f32; 4]> {
+    (0..ENVIRONMENT_RING_SIZE)
+        .map(|i| {
+            let angle = i as f32 / ENVIRONMENT_RING_SIZE as f32 * std::f32::consts::TAU;
+            let warmth = (angle.cos() * 0.5 + 0.5).powi(4);
+            [
+                0.05 + 0.9 * warmth,
+                0.08 + 0.5 * warmth,
+                0.15 + 0.1 * warmth,
+                1.0,
+            ]
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub struct RadiansOptions {
     ray_count: u32,
     accum_radiance: bool,
     max_steps: u32,
     enable_noise: bool,
     show_grain: bool,
+    boundary: BoundaryMode,
+    ambient: egui::Rgba,
+    sky_color: egui::Rgba,
+    ground_color: egui::Rgba,
+    /// Scales every boundary color, walls excluded.
+    sky_intensity: f32,
+    /// How much light surfaces pass on from the radiance around them last frame, `0`
+    /// disables bounces. Each frame adds one more bounce.
+    bounce_strength: f32,
+    /// Times a ray may bounce off mirrors before it stops.
+    max_reflections: u32,
+    /// Light absorbed per texel by fog of full painted density.
+    fog_density: f32,
+    /// Share of the absorbed light the fog scatters back out, lit by last frame's
+    /// radiance.
+    fog_scattering: f32,
+    sampling: RaySampling,
+    shadows: ShadowMode,
+    /// Marches per light under `ShadowMode::Sampled`.
+    shadow_samples: u32,
+}
+
+impl RadiansOptions {
+    /// Whether last frame's radiance is read back, by bounces or by `fog` scattering.
+    pub fn feeds_back(&self, fog: bool) -> bool {
+        self.bounce_strength > 0.0 || (fog && self.fog_density > 0.0 && self.fog_scattering > 0.0)
+    }
+
+    /// The same scene traced with `ray_count` rays of up to `max_steps` steps, each ray
+    /// jittered on its own and shadows sampled over the emitters.
+    pub fn reference(self, ray_count: u32, max_steps: u32) -> Self {
+        Self {
+            ray_count,
+            max_steps,
+            enable_noise: true,
+            show_grain: true,
+            sampling: RaySampling::Stratified,
+            shadows: ShadowMode::Sampled,
+            ..self
+        }
+    }
 }
 
 impl Default for RadiansOptions {
@@ -128,6 +517,18 @@ impl Default for RadiansOptions {
             max_steps: 128,
             enable_noise: true,
             show_grain: true,
+            boundary: BoundaryMode::Black,
+            ambient: egui::Rgba::from_rgb(0.05, 0.05, 0.06),
+            sky_color: egui::Rgba::from_rgb(0.35, 0.5, 0.8),
+            ground_color: egui::Rgba::from_rgb(0.08, 0.06, 0.04),
+            sky_intensity: 1.0,
+            bounce_strength: 0.0,
+            max_reflections: 4,
+            fog_density: 0.02,
+            fog_scattering: 0.8,
+            sampling: RaySampling::BlueNoise,
+            shadows: ShadowMode::Cone,
+            shadow_samples: 16,
         }
     }
 }
@@ -141,7 +141,7 @@ impl RadianceRenderOLDPass {
     }
 }
 
-#[derive(Debug, Clone, Copy, EguiProbe)]
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
 pub struct RadiansOptionsOLD {
     ray_count: u32,
     accum_radiance: bool,
@@ -0,0 +1,268 @@
+use egui_probe::EguiProbe;
+
+/// Side length of the tiled blue noise texture.
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+/// Void and cluster blue noise, one byte per texel.
+const BLUE_NOISE_PGM: &[u8] = include_bytes!("./blue_noise_64.pgm");
+
+/// How the rays of a pixel are spread over the circle, see `rayOffset` in the compute
+/// radiance shader. The compute mode renders a single frame, patterns moving on every
+/// frame only average out when the reference mode accumulates them.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EguiProbe)]
+pub enum RaySampling {
+    /// Hash of the pixel position, all rays turned by the same amount, every frame.
+    Hash = 0,
+    /// Tiled blue noise, all rays turned by the same amount, moving on every frame.
+    BlueNoise = 1,
+    /// Every ray jittered on its own within its share of the circle.
+    Stratified = 2,
+}
+
+/// Texels of the blue noise tile, row by row.
+pub fn blue_noise() -> &'static [u8] {
+    // Binary PGM: magic, width, height and max value, then a single whitespace.
+    let mut fields = 0;
+    let mut i = 0;
+    while fields < 4 {
+        while BLUE_NOISE_PGM[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        while !BLUE_NOISE_PGM[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        fields += 1;
+    }
+    &BLUE_NOISE_PGM[i + 1..]
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2};
+
+    use super::*;
+    use crate::render_passes::radiance_cascades_passes::reference::ImageError;
+
+    /// `rayOffset` and its helpers are mirrored below, with the constants of the shader.
+    const SHADER: &str = include_str!("./shaders/radiance_cascades_compute.wgsl");
+    const FRAME_STEP: f32 = 0.618_034;
+    const FRAME_PERIOD: u32 = 4096;
+    const RAY_STEP: f32 = 0.754_877_7;
+    const SEED_SHIFT: u32 = 1024;
+
+    const RAY_COUNT: u32 = 8;
+    const RESOLUTION: Vec2 = Vec2::new(1920.0, 1080.0);
+
+    fn pcg(value: u32) -> u32 {
+        let state = value.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+        let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+        (word >> 22) ^ word
+    }
+
+    fn seed_shift(hash: u32) -> UVec2 {
+        UVec2::new(hash % SEED_SHIFT, (hash / SEED_SHIFT) % SEED_SHIFT)
+    }
+
+    fn seed_turn(hash: u32) -> f32 {
+        (hash >> 20) as f32 / 4096.0
+    }
+
+    fn ray_offset(sampling: RaySampling, seed: u32, pixel: UVec2, frame: u32, ray: u32) -> f32 {
+        let hash = pcg(seed);
+        let pixel = pixel + seed_shift(hash);
+        let turn = seed_turn(hash);
+        if sampling == RaySampling::Hash {
+            let uv = (pixel.as_vec2() + 0.5) / RESOLUTION;
+            return fract(fract(uv.dot(Vec2::new(12.9898, 4.1414)).sin() * 43758.547) + turn);
+        }
+        let texel = pixel % BLUE_NOISE_SIZE;
+        let noise = blue_noise()[(texel.y * BLUE_NOISE_SIZE + texel.x) as usize] as f32 / 255.0;
+        let frame = (frame % FRAME_PERIOD) as f32 * FRAME_STEP;
+        if sampling == RaySampling::Stratified {
+            return fract(noise + frame + turn + ray as f32 * RAY_STEP);
+        }
+        fract(noise + frame + turn)
+    }
+
+    fn shadow_jitter(seed: u32, pixel: UVec2, frame: u32, i: u32) -> f32 {
+        let frame = pcg(seed ^ pcg(frame));
+        let hash = pcg(pixel.x ^ pcg(pixel.y ^ pcg(i ^ frame)));
+        (hash >> 8) as f32 / 16_777_216.0
+    }
+
+    /// `fract` of WGSL, which rounds down rather than towards zero.
+    fn fract(x: f32) -> f32 {
+        x - x.floor()
+    }
+
+    /// Light coming in over a 1 radian arc, lit in the same place for every pixel.
+    fn radiance(angle: f32) -> f32 {
+        let angle = angle.rem_euclid(std::f32::consts::TAU);
+        if (0.3..1.3).contains(&angle) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Error of a block of pixels against the converged image once the reference mode
+    /// averaged `frames` frames.
+    fn error(sampling: RaySampling, frames: u32) -> f32 {
+        let step = std::f32::consts::TAU / RAY_COUNT as f32;
+        let mut image = Vec::new();
+        for y in 0..32 {
+            for x in 0..32 {
+                let mut sum = 0.0;
+                for frame in 0..frames {
+                    for ray in 0..RAY_COUNT {
+                        let offset = ray_offset(sampling, 0, UVec2::new(x, y), frame, ray);
+                        sum += radiance(step * (ray as f32 + offset));
+                    }
+                }
+                image.push([sum / (frames * RAY_COUNT) as f32; 4]);
+            }
+        }
+        let converged = vec![[1.0 / std::f32::consts::TAU; 4]; image.len()];
+        ImageError::compare(&image, &converged).unwrap().rmse
+    }
+
+    #[test]
+    fn mirror_matches_the_shader() {
+        let constants = [
+            ("FRAME_STEP", FRAME_STEP as f64),
+            ("FRAME_PERIOD", FRAME_PERIOD as f64),
+            ("RAY_STEP", RAY_STEP as f64),
+            ("SEED_SHIFT", SEED_SHIFT as f64),
+        ];
+        for (name, value) in constants {
+            let line = SHADER
+                .lines()
+                .find(|line| line.starts_with(&format!("const {name}:")))
+                .unwrap();
+            let shader_value = line.split('=').nth(1).unwrap();
+            let shader_value: f64 = shader_value
+                .trim_end_matches([';', 'u'])
+                .trim()
+                .parse()
+                .unwrap();
+            assert!((shader_value - value).abs() < 1e-6, "{name}");
+        }
+    }
+
+    #[test]
+    fn offsets_stay_in_unit_range() {
+        for sampling in [
+            RaySampling::Hash,
+            RaySampling::BlueNoise,
+            RaySampling::Stratified,
+        ] {
+            for (seed, frame) in [(0, 0), (1, 1), (7, 1000), (u32::MAX, u32::MAX)] {
+                for ray in 0..RAY_COUNT {
+                    let offset = ray_offset(sampling, seed, UVec2::new(70, 3), frame, ray);
+                    assert!((0.0..1.0).contains(&offset), "{sampling:?} {offset}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_repeat_and_differ() {
+        let hashes = [1, 2].map(pcg);
+        assert_eq!(pcg(1), hashes[0]);
+        assert_ne!(seed_shift(hashes[0]), seed_shift(hashes[1]));
+        assert_ne!(seed_turn(hashes[0]), seed_turn(hashes[1]));
+        let offsets = |sampling, seed| -> Vec<f32> {
+            (0..64)
+                .map(|x| ray_offset(sampling, seed, UVec2::new(x, 5), 3, 1))
+                .collect()
+        };
+        for sampling in [
+            RaySampling::Hash,
+            RaySampling::BlueNoise,
+            RaySampling::Stratified,
+        ] {
+            assert_eq!(offsets(sampling, 1), offsets(sampling, 1));
+            assert_ne!(offsets(sampling, 1), offsets(sampling, 2), "{sampling:?}");
+        }
+    }
+
+    /// Light let through by an edge covering the emitter from `edge` on, in `-1..1`
+    /// across it, as `shadow` samples it in the sampled mode. `jitter` places the samples.
+    fn penumbra(edge: f32, frames: u32, jitter: impl Fn(u32, u32) -> f32) -> f32 {
+        const SAMPLES: u32 = 4;
+        let mut lit = 0;
+        for frame in 0..frames {
+            for i in 0..SAMPLES {
+                let t = (i as f32 + jitter(frame, i)) / SAMPLES as f32 * 2.0 - 1.0;
+                lit += (t < edge) as u32;
+            }
+        }
+        lit as f32 / (frames * SAMPLES) as f32
+    }
+
+    #[test]
+    fn shadow_samples_jitter_on_their_own() {
+        let pixel = UVec2::new(70, 3);
+        // Gaps between the samples of a frame, equal when they share one offset.
+        let gaps = |jitter: &dyn Fn(u32) -> f32| -> Vec<f32> {
+            let t: Vec<f32> = (0..4).map(|i| i as f32 + jitter(i)).collect();
+            t.windows(2).map(|pair| pair[1] - pair[0]).collect()
+        };
+        for sampling in [RaySampling::Hash, RaySampling::BlueNoise] {
+            let shared = gaps(&|i| ray_offset(sampling, 0, pixel, 0, i));
+            assert!(
+                shared.iter().all(|gap| (gap - 1.0).abs() < 1e-5),
+                "{shared:?}"
+            );
+        }
+        let jittered = gaps(&|i| shadow_jitter(0, pixel, 0, i));
+        assert!(
+            jittered.iter().any(|gap| (gap - 1.0).abs() > 1e-2),
+            "{jittered:?}"
+        );
+
+        // The cone mode dims by the covered share of the emitter, which the sampled
+        // mode converges to whatever the ray sampling.
+        let error = |jitter: &dyn Fn(u32, u32) -> f32| -> f32 {
+            [-0.9, -0.3, 0.1, 0.45, 0.8]
+                .into_iter()
+                .map(|edge| (penumbra(edge, 256, jitter) - (edge + 1.0) / 2.0).abs())
+                .fold(0.0, f32::max)
+        };
+        let sampled = error(&|frame, i| shadow_jitter(0, pixel, frame, i));
+        assert!(sampled < 0.03, "{sampled}");
+        let hash = error(&|frame, i| ray_offset(RaySampling::Hash, 0, pixel, frame, i));
+        assert!(hash > sampled * 2.0, "{hash} {sampled}");
+    }
+
+    #[test]
+    fn hash_does_not_converge_over_frames() {
+        let single = error(RaySampling::Hash, 1);
+        let many = error(RaySampling::Hash, 64);
+        assert!((single - many).abs() < 1e-6, "{single} {many}");
+    }
+
+    #[test]
+    fn frame_rotation_converges() {
+        let hash = error(RaySampling::Hash, 64);
+        for sampling in [RaySampling::BlueNoise, RaySampling::Stratified] {
+            let single = error(sampling, 1);
+            let many = error(sampling, 64);
+            assert!(many < single * 0.1, "{sampling:?} {single} {many}");
+            assert!(many < hash * 0.1, "{sampling:?} {hash} {many}");
+        }
+    }
+
+    #[test]
+    fn blue_noise_is_a_full_tile() {
+        let noise = blue_noise();
+        assert_eq!(noise.len(), (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as usize);
+        // Every level shows up equally often.
+        let mut counts = [0; 256];
+        for &value in noise {
+            counts[value as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| count == 16));
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::Write;
+
+use bytemuck::{Pod, Zeroable, bytes_of};
+use egui_probe::EguiProbe;
+use wgpu::{CommandEncoder, ComputePipelineDescriptor, Device, PushConstantRange, ShaderStages};
+
+use crate::{
+    render_passes::radiance_cascades_passes::radiance_render_compute::RadiansOptions,
+    texture_manager::{TextureManager, textures::EngineTexture},
+};
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+struct ReferenceAccumulateConstants {
+    samples: u32,
+}
+
+/// Brute force tracing of the compute mode, averaged over frames until it converges.
+/// Bounces and fog scattering read back the average so far, so they converge to every
+/// order along with it.
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe)]
+pub struct ReferenceOptions {
+    /// Rays per pixel and frame.
+    ray_count: u32,
+    max_steps: u32,
+    /// Frames averaged before it stops, `0` keeps going. The ray jitter repeats after
+    /// 4096 frames, more add nothing.
+    max_frames: u32,
+}
+
+impl ReferenceOptions {
+    /// Options of the compute pass tracing a single frame of the `scene` the compute
+    /// mode renders.
+    pub fn trace_options(&self, scene: RadiansOptions) -> RadiansOptions {
+        scene.reference(self.ray_count, self.max_steps)
+    }
+
+    pub fn converged(&self, samples: u32) -> bool {
+        self.max_frames != 0 && samples >= self.max_frames
+    }
+}
+
+impl Default for ReferenceOptions {
+    fn default() -> Self {
+        Self {
+            ray_count: 64,
+            max_steps: 1024,
+            max_frames: 1024,
+        }
+    }
+}
+
+/// Sums the frames of "RadianceCascades" into "RadianceReferenceSum" and replaces them
+/// with the average.
+pub struct ReferenceAccumulatePass {
+    compute_pipeline: wgpu::ComputePipeline,
+    /// Frames in the sum.
+    samples: u32,
+}
+
+impl ReferenceAccumulatePass {
+    pub fn new(device: &Device, texture_manager: &TextureManager) -> Self {
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("./shaders/reference_accumulate.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reference accumulate pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                texture_manager.get_compute_mut_bind_group_layout(),
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<ReferenceAccumulateConstants>() as u32,
+            }],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Reference accumulate pass"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+        Self {
+            compute_pipeline,
+            samples: 0,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Drops the sum, the next frame starts it over.
+    pub fn restart(&mut self) {
+        self.samples = 0;
+    }
+
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        texture_manager: &TextureManager,
+        width: u32,
+        height: u32,
+    ) {
+        let (Some(frame), Some(sum)) = (
+            texture_manager.get_texture("RadianceCascades"),
+            texture_manager.get_texture("RadianceReferenceSum"),
+        ) else {
+            return;
+        };
+        self.samples += 1;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Reference accumulate pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_push_constants(
+            0,
+            bytes_of(&ReferenceAccumulateConstants {
+                samples: self.samples,
+            }),
+        );
+        compute_pass.set_bind_group(0, frame.compute_mut_group_f32(), &[]);
+        compute_pass.set_bind_group(1, sum.compute_mut_group_f32(), &[]);
+        compute_pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+    }
+}
+
+/// How far an image is from a reference, over the rgb channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageError {
+    /// Root mean square error.
+    pub rmse: f32,
+    /// Peak signal to noise ratio in dB, the peak being the brightest reference channel
+    /// but at least 1, so dark references still give a finite ratio. Infinite for
+    /// identical images.
+    pub psnr: f32,
+}
+
+impl ImageError {
+    /// `None` if the images differ in size or are empty.
+    pub fn compare(image: &[[f32; 4]], reference: &[[f32; 4]]) -> Option<Self> {
+        if image.len() != reference.len() || image.is_empty() {
+            return None;
+        }
+        let mut squared = 0.0f64;
+        let mut peak = 1.0f32;
+        for (pixel, expected) in image.iter().zip(reference) {
+            for channel in 0..3 {
+                squared += ((pixel[channel] - expected[channel]) as f64).powi(2);
+                peak = peak.max(expected[channel]);
+            }
+        }
+        let rmse = (squared / (image.len() * 3) as f64).sqrt() as f32;
+        let psnr = if rmse == 0.0 {
+            f32::INFINITY
+        } else {
+            20.0 * (peak / rmse).log10()
+        };
+        Some(Self { rmse, psnr })
+    }
+}
+
+/// Writes `pixels`, rows top to bottom, as a little endian color PFM. Alpha is dropped.
+pub fn write_pfm(
+    writer: &mut impl Write,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+) -> std::io::Result<()> {
+    write!(writer, "PF\n{width} {height}\n-1.0\n")?;
+    // PFM rows go bottom to top.
+    for row in pixels.chunks_exact(width as usize).rev() {
+        for pixel in row {
+            for channel in &pixel[..3] {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_images_have_no_error() {
+        let image = vec![[0.5, 1.0, 0.25, 1.0]; 16];
+        let error = ImageError::compare(&image, &image).unwrap();
+        assert_eq!(error.rmse, 0.0);
+        assert_eq!(error.psnr, f32::INFINITY);
+    }
+
+    #[test]
+    fn error_against_reference() {
+        let reference = vec![[1.0, 1.0, 1.0, 1.0]; 4];
+        // Alpha is ignored.
+        let image = vec![[0.9, 0.9, 0.9, 0.0]; 4];
+        let error = ImageError::compare(&image, &reference).unwrap();
+        assert!((error.rmse - 0.1).abs() < 1e-6, "{error:?}");
+        assert!((error.psnr - 20.0).abs() < 1e-4, "{error:?}");
+        assert_eq!(ImageError::compare(&image[..2], &reference), None);
+    }
+
+    #[test]
+    fn black_references_have_a_unit_peak() {
+        let black = vec![[0.0, 0.0, 0.0, 1.0]; 4];
+        let error = ImageError::compare(&black, &black).unwrap();
+        assert_eq!(error.rmse, 0.0);
+        assert_eq!(error.psnr, f32::INFINITY);
+        let image = vec![[0.1, 0.1, 0.1, 1.0]; 4];
+        let error = ImageError::compare(&image, &black).unwrap();
+        assert!((error.rmse - 0.1).abs() < 1e-6, "{error:?}");
+        assert!((error.psnr - 20.0).abs() < 1e-4, "{error:?}");
+    }
+
+    #[test]
+    fn pfm_rows_go_bottom_to_top() {
+        let pixels = [[1.0, 2.0, 3.0, 9.0], [4.0, 5.0, 6.0, 9.0]];
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, 1, 2, &pixels).unwrap();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [4.0, 5.0, 6.0, 1.0, 2.0, 3.0]);
+    }
+}
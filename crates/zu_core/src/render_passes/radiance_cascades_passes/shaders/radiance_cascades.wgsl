@@ -14,6 +14,7 @@ struct PushConstants {
     max_steps: i32,
     enable_noise: i32,
     show_grain: i32,
+    seed: u32,
     resolution: vec2<f32>
 }
 
@@ -27,6 +28,7 @@ struct VertexOutput {
 const PI: f32 = 3.14159265;
 const TAU: f32 = 2.0 * PI;
 const EPS = 0.001f;
+const SEED_SHIFT: u32 = 1024u;
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
@@ -40,7 +42,7 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     var one_over_ray_count = 1.0 / f32(constants.ray_count);
     var angleStepSize = TAU * one_over_ray_count;
 
-    var offset = select(0.0, rand22(uv), constants.enable_noise != 0);
+    var offset = select(0.0, seededRand(uv), constants.enable_noise != 0);
     var rayAngleStepSize = select(angleStepSize + offset * TAU, angleStepSize, constants.show_grain != 0);
 
     for (var i = 0; i < constants.ray_count; i++) {
@@ -75,4 +77,18 @@ fn outOfBounds(uv: vec2<f32>) -> bool {
     return uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0;
 }
 
+// Hash noise moved around by the seed like `rayOffset` in the compute shader.
+fn seededRand(uv: vec2<f32>) -> f32 {
+    let hash = pcg(constants.seed);
+    let shift = vec2(hash % SEED_SHIFT, (hash / SEED_SHIFT) % SEED_SHIFT);
+    let size = vec2<f32>(textureDimensions(scene_texture));
+    return fract(rand22(uv + vec2<f32>(shift) / size) + f32(hash >> 20u) / 4096.0);
+}
+
+fn pcg(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
 fn rand22(n: vec2f) -> f32 { return fract(sin(dot(n, vec2f(12.9898, 4.1414))) * 43758.5453); }
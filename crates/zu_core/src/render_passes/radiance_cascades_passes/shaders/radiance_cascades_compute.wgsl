@@ -4,6 +4,33 @@
 
 @group(2) @binding(0) var output_texture: texture_storage_2d<rgba32float, read_write>;
 
+struct Light {
+    position: vec2<f32>,
+    axis: vec2<f32>,
+    half_size: vec2<f32>,
+    radius: f32,
+    cos_cone: f32,
+    color: vec3<f32>,
+    kind: u32,
+    source_radius: f32,
+    // World units above the ground, risen per texel for the sun.
+    height: f32,
+};
+
+@group(3) @binding(0) var<storage, read> lights: array<Light>;
+@group(3) @binding(1) var<storage, read> environment_ring: array<vec4<f32>>;
+@group(3) @binding(2) var previous_radiance: texture_2d<f32>;
+// Color let through by translucent texels, black for opaque ones. Alpha is 1 on mirrors.
+@group(3) @binding(3) var material_texture: texture_2d<f32>;
+// Fog color, and its density in alpha.
+@group(3) @binding(4) var fog_texture: texture_2d<f32>;
+@group(3) @binding(5) var blue_noise: texture_2d<f32>;
+// Average radiance times the x and y of the direction it arrives from.
+@group(3) @binding(6) var harmonics_x: texture_storage_2d<rgba32float, write>;
+@group(3) @binding(7) var harmonics_y: texture_storage_2d<rgba32float, write>;
+// How high the surfaces reach in red, in world units.
+@group(3) @binding(8) var height_texture: texture_2d<f32>;
+
 
 struct PushConstants {
     ray_count: i32,
@@ -11,9 +38,23 @@ struct PushConstants {
     max_steps: i32,
     enable_noise: i32,
     show_grain: i32,
-    _padding0: i32,
+    light_count: u32,
     resolution: vec2<f32>,
-    _padding1: vec2<f32>
+    boundary_mode: u32,
+    sky_intensity: f32,
+    shadow_mode: u32,
+    shadow_samples: u32,
+    ambient: vec4<f32>,
+    sky_color: vec4<f32>,
+    ground_color: vec4<f32>,
+    bounce_strength: f32,
+    max_reflections: u32,
+    fog_density: f32,
+    fog_scattering: f32,
+    frame_index: u32,
+    sampling: u32,
+    seed: u32,
+    _padding3: u32,
 };
 
 
@@ -23,56 +64,417 @@ var<push_constant> constants: PushConstants;
 const PI: f32 = 3.14159265;
 const TAU: f32 = 2.0 * PI;
 const EPS = 0.001f;
+// Longest step in texels taken while fog is on, the distance field does not see fog.
+const FOG_STEP = 4.0;
+
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+const LIGHT_LINE: u32 = 2u;
+const LIGHT_AREA: u32 = 3u;
+const LIGHT_SUN: u32 = 4u;
+
+const BOUNDARY_BLACK: u32 = 0u;
+const BOUNDARY_AMBIENT: u32 = 1u;
+const BOUNDARY_SKY_GRADIENT: u32 = 2u;
+const BOUNDARY_SKY_RING: u32 = 3u;
+const BOUNDARY_WALLS: u32 = 4u;
+
+const SHADOW_HARD: u32 = 0u;
+const SHADOW_CONE: u32 = 1u;
+const SHADOW_SAMPLED: u32 = 2u;
+
+const SAMPLING_HASH: u32 = 0u;
+const SAMPLING_BLUE_NOISE: u32 = 1u;
+const SAMPLING_STRATIFIED: u32 = 2u;
+
+// Frames move the jitter along the R1 sequence, starting over before f32 loses precision.
+const FRAME_STEP: f32 = 0.618034;
+const FRAME_PERIOD: u32 = 4096u;
+const RAY_STEP: f32 = 0.7548777;
+const SEED_SHIFT: u32 = 1024u;
 
 @compute @workgroup_size(16, 16)
 fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(output_texture)) {
+        return;
+    }
     let pixelCoord = id.xy;
     let uv = (vec2<f32>(pixelCoord) + 0.5) / constants.resolution;
 
     let light = textureLoad(scene_texture, pixelCoord, 0);
-    if light.a > 0.1 {
+    let translucent = isTranslucent(vec2<i32>(pixelCoord));
+    if light.a > 0.1 && !translucent {
         textureStore(output_texture, vec2<i32>(pixelCoord), light);
+        textureStore(harmonics_x, vec2<i32>(pixelCoord), vec4(0.0));
+        textureStore(harmonics_y, vec2<i32>(pixelCoord), vec4(0.0));
         return;
     }
     var radiance = vec4(0.0);
+    var radiance_x = vec3(0.0);
+    var radiance_y = vec3(0.0);
 
     var one_over_ray_count = 1.0 / f32(constants.ray_count);
     var angleStepSize = TAU * one_over_ray_count;
 
-    var offset = select(0.0, rand22(uv), constants.enable_noise != 0);
+    let noise_enabled = constants.enable_noise != 0;
+    var offset = select(0.0, rayOffset(pixelCoord, 0u), noise_enabled);
     var rayAngleStepSize = select(angleStepSize + offset * TAU, angleStepSize, constants.show_grain != 0);
     let pixelSize = 1.0 / constants.resolution;
+    let texelStep = min(pixelSize.x, pixelSize.y);
 
+    // The rays run along the ground, so every surface blocks them whatever its height.
+    // Only the analytic lights, which sit above the ground, pass over low surfaces.
     for (var i = 0; i < constants.ray_count; i++) {
+        if noise_enabled && constants.sampling == SAMPLING_STRATIFIED {
+            offset = rayOffset(pixelCoord, u32(i));
+        }
         var angle = rayAngleStepSize * (f32(i) + offset);
+        var reflections = 0u;
         var rayDirection = vec2(cos(angle), -sin(angle));
+        let arrival = rayDirection;
 
         var sample_uv = uv;
         var radDelta = vec4(0.0);
-        var hitSurface = false;
+        // Light left after the translucent surfaces crossed so far.
+        var throughput = vec4(1.0);
+        var inside = translucent;
         for (var step = 1; step < constants.max_steps; step++) {
 
             var sample_px = vec2<i32>(sample_uv * constants.resolution);
             var dist = textureLoad(distance_texture, sample_px, 0).r;
 
-            sample_uv += rayDirection * dist;
-
-            if outOfBounds(sample_uv) {break;}
-
+            var advance = dist;
             if dist < EPS {
                 var sampleColor = textureLoad(scene_texture, sample_px, 0);
-                radDelta += sampleColor;
-                hitSurface = true;
+                if sampleColor.a > 0.1 && isMirror(sample_px) {
+                    if reflections >= constants.max_reflections {
+                        break;
+                    }
+                    reflections++;
+                    // Back out of the mirror and carry on in the reflected direction.
+                    sample_uv -= rayDirection * texelStep * 2.0;
+                    rayDirection = reflect(rayDirection, surfaceNormal(sample_uv, rayDirection, pixelSize));
+                    angle = atan2(-rayDirection.y, rayDirection.x);
+                    throughput *= vec4(sampleColor.rgb, 1.0);
+                    if all(throughput.rgb < vec3(EPS)) {
+                        break;
+                    }
+                    inside = false;
+                    continue;
+                }
+                if sampleColor.a > 0.1 && !isTranslucent(sample_px) {
+                    radDelta += throughput * sampleColor;
+                    if constants.bounce_strength > 0.0 {
+                        radDelta += vec4(throughput.rgb * sampleColor.rgb * bounceLight(sample_uv, rayDirection, pixelSize), 0.0);
+                    }
+                    break;
+                }
+                // Translucent surfaces, and the open texels right next to any surface,
+                // are crossed a texel at a time.
+                let entering = sampleColor.a > 0.1 && !inside;
+                if entering {
+                    radDelta += throughput * vec4(sampleColor.rgb, 0.0);
+                    throughput *= vec4(textureLoad(material_texture, sample_px, 0).rgb, 1.0);
+                    if all(throughput.rgb < vec3(EPS)) {
+                        break;
+                    }
+                }
+                inside = sampleColor.a > 0.1;
+                advance = texelStep;
+            } else {
+                inside = false;
+                if constants.fog_density > 0.0 {
+                    advance = min(dist, texelStep * FOG_STEP);
+                    let fog = textureLoad(fog_texture, sample_px, 0);
+                    if fog.a > 0.0 {
+                        let absorbed = 1.0 - exp(-fog.a * constants.fog_density * advance / texelStep);
+                        let lit = textureLoad(previous_radiance, sample_px, 0).rgb;
+                        radDelta += vec4(throughput.rgb * absorbed * constants.fog_scattering * fog.rgb * lit, 0.0);
+                        throughput *= vec4(vec3(1.0 - absorbed), 1.0);
+                        if all(throughput.rgb < vec3(EPS)) {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            sample_uv += rayDirection * advance;
+
+            if outOfBounds(sample_uv) {
+                radDelta += throughput * boundary(sample_uv, angle);
                 break;
             }
         }
         radiance += radDelta;
+        radiance_x += radDelta.rgb * arrival.x;
+        radiance_y += radDelta.rgb * arrival.y;
+    }
+    let direct = analyticLights(vec2<f32>(pixelCoord) + 0.5);
+    // Translucent texels glow with whatever they do not let through.
+    let own = select(vec3(0.0), light.rgb, translucent);
+    textureStore(output_texture, vec2<i32>(pixelCoord), vec4((radiance * one_over_ray_count).xyz + direct.total + own, 1.0));
+    textureStore(harmonics_x, vec2<i32>(pixelCoord), vec4(radiance_x * one_over_ray_count + direct.x, 0.0));
+    textureStore(harmonics_y, vec2<i32>(pixelCoord), vec4(radiance_y * one_over_ray_count + direct.y, 0.0));
+}
+
+struct DirectLight {
+    total: vec3<f32>,
+    // Weighted by the direction towards the lights.
+    x: vec3<f32>,
+    y: vec3<f32>,
+};
+
+fn analyticLights(pixel: vec2<f32>) -> DirectLight {
+    var direct = DirectLight(vec3(0.0), vec3(0.0), vec3(0.0));
+    for (var i = 0u; i < constants.light_count; i++) {
+        let light = lights[i];
+        let color = lightContribution(light, pixel);
+        var direction = -light.axis;
+        if light.kind != LIGHT_SUN {
+            let toward = lightSource(light, pixel) - pixel;
+            direction = toward / max(length(toward), EPS);
+        }
+        direct.total += color;
+        direct.x += color * direction.x;
+        direct.y += color * direction.y;
+    }
+    return direct;
+}
+
+// Point of the light closest to `pixel`.
+fn lightSource(light: Light, pixel: vec2<f32>) -> vec2<f32> {
+    if light.kind == LIGHT_LINE {
+        let along = clamp(dot(pixel - light.position, light.axis), -light.half_size.x, light.half_size.x);
+        return light.position + light.axis * along;
+    }
+    if light.kind == LIGHT_AREA {
+        let normal = vec2(-light.axis.y, light.axis.x);
+        let local = vec2(dot(pixel - light.position, light.axis), dot(pixel - light.position, normal));
+        let closest = clamp(local, -light.half_size, light.half_size);
+        return light.position + light.axis * closest.x + normal * closest.y;
+    }
+    return light.position;
+}
+
+fn lightContribution(light: Light, pixel: vec2<f32>) -> vec3<f32> {
+    if light.kind == LIGHT_SUN {
+        // Anything between the pixel and the canvas edge shadows it.
+        let far = constants.resolution.x + constants.resolution.y;
+        return light.color * shadow(pixel, -light.axis * far, light.source_radius * far, light.height);
+    }
+    let source = lightSource(light, pixel);
+    let offset = pixel - source;
+    let distance = length(offset);
+    if distance >= light.radius {
+        return vec3(0.0);
+    }
+    var falloff = 1.0 - distance / light.radius;
+    falloff *= falloff;
+    if light.kind == LIGHT_SPOT {
+        let cosine = dot(offset / max(distance, EPS), light.axis);
+        falloff *= smoothstep(light.cos_cone, min(light.cos_cone + 0.05, 1.0), cosine);
+    }
+    if falloff <= 0.0 {
+        return vec3(0.0);
+    }
+    let rise = light.height / max(distance, EPS);
+    return light.color * falloff * shadow(pixel, -offset, light.source_radius, rise);
+}
+
+// Light reaching `pixel` from an emitter `radius` texels wide at `pixel + toward`, along
+// rays rising by `rise` world units per texel.
+fn shadow(pixel: vec2<f32>, toward: vec2<f32>, radius: f32, rise: f32) -> vec3<f32> {
+    let len = length(toward);
+    if constants.shadow_mode == SHADOW_HARD || radius <= 0.0 || len < EPS {
+        return visibility(pixel, pixel + toward, 0.0, rise);
+    }
+    if constants.shadow_mode == SHADOW_CONE {
+        return visibility(pixel, pixel + toward, radius / len, rise);
+    }
+    // Seen from the pixel, the emitter is a segment across the direction towards it.
+    let across = vec2(-toward.y, toward.x) / len * radius;
+    let samples = max(constants.shadow_samples, 1u);
+    var total = vec3(0.0);
+    for (var i = 0u; i < samples; i++) {
+        let t = (f32(i) + shadowJitter(vec2<u32>(pixel), i)) / f32(samples) * 2.0 - 1.0;
+        total += visibility(pixel, pixel + toward + across * t, 0.0, rise);
+    }
+    return total / f32(samples);
+}
+
+// Light let through along the segment between two canvas texels, 0 where something opaque
+// blocks it. With a `spread` above 0 the segment is the axis of a cone widening by that
+// many texels per texel, dimmed where surfaces reach into it. The segment starts on the
+// ground and rises by `rise` world units per texel, passing over lower surfaces.
+fn visibility(start: vec2<f32>, end: vec2<f32>, spread: f32, rise: f32) -> vec3<f32> {
+    let delta = end - start;
+    let len = length(delta);
+    let direction = delta / max(len, EPS);
+    // The distance field is in uv units, which are never shorter than this many texels.
+    let texels_per_uv = min(constants.resolution.x, constants.resolution.y);
+    var transmitted = vec3(1.0);
+    var inside = false;
+    var traveled = 1.0;
+    var lit = 1.0;
+    for (var step = 0; step < constants.max_steps && traveled < len - 1.0; step++) {
+        let sample_px = start + direction * traveled;
+        if outOfBounds(sample_px / constants.resolution) {
+            return select(transmitted * lit, vec3(0.0), constants.boundary_mode == BOUNDARY_WALLS);
+        }
+        let texel = vec2<i32>(sample_px);
+        let field = textureLoad(distance_texture, texel, 0);
+        let dist = field.r * texels_per_uv;
+        let ray_height = rise * traveled;
+        if dist < 0.5 {
+            if ray_height > surfaceHeight(texel) {
+                inside = false;
+                traveled += 1.0;
+                continue;
+            }
+            if !isTranslucent(texel) {
+                return vec3(0.0);
+            }
+            if !inside {
+                transmitted *= textureLoad(material_texture, texel, 0).rgb;
+                inside = true;
+            }
+            traveled += 1.0;
+            continue;
+        }
+        inside = false;
+        // The distance field keeps the nearest surface, the cone passes over it if it is low.
+        let nearest = vec2<i32>(field.gb * constants.resolution);
+        if spread > 0.0 && ray_height <= surfaceHeight(nearest) {
+            lit = min(lit, clamp(dist / (spread * traveled), 0.0, 1.0));
+        }
+        traveled += dist;
+    }
+    return transmitted * lit;
+}
+
+fn surfaceHeight(px: vec2<i32>) -> f32 {
+    let size = vec2<i32>(constants.resolution) - 1;
+    return textureLoad(height_texture, clamp(px, vec2(0), size), 0).r;
+}
+
+fn isMirror(px: vec2<i32>) -> bool {
+    return textureLoad(material_texture, px, 0).a > 0.5;
+}
+
+// Normal of the surface closest to `sample_uv`, from the distance field gradient. Faces
+// the ray where the gradient vanishes.
+fn surfaceNormal(sample_uv: vec2<f32>, direction: vec2<f32>, pixelSize: vec2<f32>) -> vec2<f32> {
+    let dx = vec2(pixelSize.x * 2.0, 0.0);
+    let dy = vec2(0.0, pixelSize.y * 2.0);
+    let gradient = vec2(
+        distanceAt(sample_uv + dx) - distanceAt(sample_uv - dx),
+        distanceAt(sample_uv + dy) - distanceAt(sample_uv - dy),
+    );
+    let len = length(gradient);
+    if len < 1e-6 {
+        return -direction;
+    }
+    return gradient / len;
+}
+
+fn distanceAt(sample_uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(constants.resolution) - 1;
+    let px = clamp(vec2<i32>(sample_uv * constants.resolution), vec2(0), size);
+    return textureLoad(distance_texture, px, 0).r;
+}
+
+fn isTranslucent(px: vec2<i32>) -> bool {
+    return any(textureLoad(material_texture, px, 0).rgb > vec3(0.0));
+}
+
+// Light the surface hit at `hit_uv` received last frame, read from the open texels the
+// ray came through.
+fn bounceLight(hit_uv: vec2<f32>, direction: vec2<f32>, pixelSize: vec2<f32>) -> vec3<f32> {
+    let size = vec2<i32>(constants.resolution) - 1;
+    for (var back = 1; back <= 3; back++) {
+        let px = clamp(vec2<i32>((hit_uv - direction * pixelSize * f32(back)) * constants.resolution), vec2(0), size);
+        if textureLoad(scene_texture, px, 0).a <= 0.1 {
+            return textureLoad(previous_radiance, px, 0).rgb * constants.bounce_strength;
+        }
+    }
+    return vec3(0.0);
+}
+
+// Light arriving along a ray that left the canvas at `exit_uv`, going at `angle`.
+fn boundary(exit_uv: vec2<f32>, angle: f32) -> vec4<f32> {
+    switch constants.boundary_mode {
+        case BOUNDARY_AMBIENT: {
+            return constants.ambient * constants.sky_intensity;
+        }
+        case BOUNDARY_SKY_GRADIENT: {
+            // Angles go counterclockwise with y up.
+            let up = sin(angle) * 0.5 + 0.5;
+            return mix(constants.ground_color, constants.sky_color, up) * constants.sky_intensity;
+        }
+        case BOUNDARY_SKY_RING: {
+            let count = arrayLength(&environment_ring);
+            let position = fract(angle / TAU) * f32(count);
+            let index = u32(position) % count;
+            let next = (index + 1u) % count;
+            let color = mix(environment_ring[index], environment_ring[next], fract(position));
+            return color * constants.sky_intensity;
+        }
+        case BOUNDARY_WALLS: {
+            let edge = clamp(vec2<i32>(exit_uv * constants.resolution), vec2(0), vec2<i32>(constants.resolution) - 1);
+            return textureLoad(scene_texture, edge, 0);
+        }
+        default: {
+            return vec4(0.0);
+        }
     }
-    textureStore(output_texture, vec2<i32>(pixelCoord), vec4((radiance * one_over_ray_count).xyz, 1.0));
 }
 
 fn outOfBounds(uv: vec2<f32>) -> bool {
     return uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0;
 }
 
+// Where within its angle step a ray is cast, in `0..1`. Mirrored by the tests of
+// ray_sampling.rs, keep them in step.
+fn rayOffset(pixelCoord: vec2<u32>, ray: u32) -> f32 {
+    let hash = pcg(constants.seed);
+    let pixel = pixelCoord + seedShift(hash);
+    let turn = seedTurn(hash);
+    if constants.sampling == SAMPLING_HASH {
+        let uv = (vec2<f32>(pixel) + 0.5) / constants.resolution;
+        return fract(rand22(uv) + turn);
+    }
+    let size = textureDimensions(blue_noise);
+    let noise = textureLoad(blue_noise, pixel % size, 0).r;
+    let frame = f32(constants.frame_index % FRAME_PERIOD) * FRAME_STEP;
+    if constants.sampling == SAMPLING_STRATIFIED {
+        return fract(noise + frame + turn + f32(ray) * RAY_STEP);
+    }
+    return fract(noise + frame + turn);
+}
+
+// Where within its share of the emitter shadow sample `i` is taken, in `0..1`. Every
+// sample is jittered on its own whatever the ray sampling, so the penumbra does not band.
+// Mirrored by the tests of ray_sampling.rs as well.
+fn shadowJitter(pixel: vec2<u32>, i: u32) -> f32 {
+    let frame = pcg(constants.seed ^ pcg(constants.frame_index));
+    let hash = pcg(pixel.x ^ pcg(pixel.y ^ pcg(i ^ frame)));
+    return f32(hash >> 8u) / 16777216.0;
+}
+
+// Texels the noise pattern is shifted by under the seed.
+fn seedShift(hash: u32) -> vec2<u32> {
+    return vec2(hash % SEED_SHIFT, (hash / SEED_SHIFT) % SEED_SHIFT);
+}
+
+// Turn added to every ray under the seed.
+fn seedTurn(hash: u32) -> f32 {
+    return f32(hash >> 20u) / 4096.0;
+}
+
+fn pcg(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
 fn rand22(n: vec2f) -> f32 { return fract(sin(dot(n, vec2f(12.9898, 4.1414))) * 43758.5453); }
@@ -0,0 +1,24 @@
+@group(0) @binding(0) var frame_texture: texture_storage_2d<rgba32float, read_write>;
+@group(1) @binding(0) var sum_texture: texture_storage_2d<rgba32float, read_write>;
+
+struct PushConstants {
+    // Frames in the sum, this one included.
+    samples: u32,
+};
+
+var<push_constant> constants: PushConstants;
+
+@compute @workgroup_size(16, 16)
+fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pixelCoord = vec2<i32>(id.xy);
+    if any(id.xy >= textureDimensions(frame_texture)) {
+        return;
+    }
+    var sum = textureLoad(frame_texture, pixelCoord);
+    if constants.samples > 1u {
+        sum += textureLoad(sum_texture, pixelCoord);
+    }
+    textureStore(sum_texture, pixelCoord, sum);
+    // The frame is replaced by the mean, the next one bounces off it.
+    textureStore(frame_texture, pixelCoord, sum / f32(constants.samples));
+}
@@ -1,30 +1,93 @@
 use egui_probe::EguiProbe;
-use glam::Vec2;
+use glam::{IVec2, Vec2};
 use wgpu::{
     CommandEncoder, Device, Queue, TextureView,
 };
 
 use crate::{
+    brush::tools::Tool,
+    camera::Camera,
+    history::History,
+    layers::{Layer, LayerStack},
+    lights::{Light, LightData},
     render_passes::{
+        brush_pass::{BrushPass, BrushStamp},
         distant_field_pass::DistantFieldPass,
+        flood_fill_pass::FloodFillPass,
         jfa_passes::{
             JfaPassesManager, JfaRenderOptions,
         },
+        layer_composite_pass::LayerCompositePass,
+        lit_sprite_pass::{LitSprite, LitSpriteId, LitSpritePass},
+        object_render_pass::{ObjectRenderPass, SceneObject, SpriteId},
         quad_vertex::QuadVertexRenderPass,
-        radiance_cascades_passes::{RadianceCascadesPassesManager, RadianceCascadesRenderOptions},
-        show_pass::ShowRenderPass,
+        radiance_cascades_passes::{
+            RadianceCascadesPassesManager, RadianceCascadesRenderOptions,
+            reference::ImageError,
+        },
+        show_pass::{CanvasTransform, ShowRenderPass},
     },
     texture_manager::{
         TextureManager,
-        textures::{EngineTexture, ManagedTexture, TextureType},
+        dirty_rect::DirtyRect,
+        readback::Readback,
+        textures::{EngineTexture, ManagedTexture, TextureType, scene_texture::SceneTexture},
     },
+    world::{ChunkedWorld, TexelGrid},
 };
 
+/// Frames the radiance keeps being rendered after a change while it feeds back into
+/// itself, one more bounce or fog scattering each.
+const BOUNCE_FRAMES: u32 = 8;
+
+/// What the radiance is read back for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadianceReadback {
+    /// Keep it as the reference.
+    Save,
+    /// Compare it to the reference.
+    Compare,
+}
+
+/// A finished radiance readback.
+#[derive(Debug)]
+pub enum ReferenceReadback {
+    /// The radiance now kept as the reference.
+    Saved {
+        width: u32,
+        height: u32,
+        pixels: Vec<[f32; 4]>,
+    },
+    /// Error against the reference, `None` once the canvas was resized.
+    Compared(Option<ImageError>),
+}
+
+/// Where the old contents end up in the world when the canvas is resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasAnchor {
+    TopLeft,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanvasSettings {
+    pub width: u32,
+    pub height: u32,
+    pub anchor: CanvasAnchor,
+}
+
 #[derive(Debug, Clone, EguiProbe)]
 pub struct RenderOptions {
     radiance_options: RadianceCascadesRenderOptions,
     jfa_options: JfaRenderOptions,
     show: String,
+    skip_unchanged_passes: bool,
+    /// Texels around the view kept on the canvas, so off-screen occluders and lights
+    /// still contribute.
+    stream_margin: u32,
+    /// Seeds all the noise, on the CPU and the GPU. The same seed and inputs render the
+    /// same frames.
+    seed: u32,
 }
 
 impl Default for RenderOptions {
@@ -33,11 +96,34 @@ impl Default for RenderOptions {
             radiance_options: Default::default(),
             jfa_options: Default::default(),
             show: "RadianceCascades".into(),
+            skip_unchanged_passes: true,
+            stream_margin: 128,
+            seed: 0,
         }
     }
 }
 
+/// Inputs the cached pass outputs were last rendered with.
+#[derive(Default)]
+struct PassCache {
+    scene_generation: Option<u64>,
+    jfa_options: Option<JfaRenderOptions>,
+    radiance_options: Option<RadianceCascadesRenderOptions>,
+    lights: Option<Vec<LightData>>,
+}
+
+impl PassCache {
+    fn invalidate(&mut self) {
+        *self = Default::default();
+    }
+}
+
 pub struct RenderPassManager {
+    brush_pass: BrushPass,
+    flood_fill_pass: FloodFillPass,
+    layer_composite_pass: LayerCompositePass,
+    object_render_pass: ObjectRenderPass,
+    lit_sprite_pass: LitSpritePass,
     distant_field_pass: DistantFieldPass,
     show_pass: ShowRenderPass,
     quad_render_pass: QuadVertexRenderPass,
@@ -45,6 +131,29 @@ pub struct RenderPassManager {
     texture_manager: TextureManager,
     radiance_passes_manager: RadianceCascadesPassesManager,
     jfa_passes_manager: JfaPassesManager,
+    pass_cache: PassCache,
+    /// Layers as of the last composite, any change to them recomposites everything.
+    composited_layers: Option<Vec<Layer>>,
+    /// Bounce frames left to render.
+    pending_bounces: u32,
+    /// The distance field was last updated around painted regions only.
+    jfa_partial: bool,
+    /// Seed the passes were last seeded with.
+    seed: u32,
+    /// Canvas texels covered by the objects drawn last frame.
+    object_regions: Vec<DirtyRect>,
+    /// Objects drawn last frame, unchanged objects are only drawn again when the layers
+    /// under them are recomposited.
+    drawn_objects: Vec<SceneObject>,
+    /// Radiance saved with `RadianceReadback::Save`.
+    reference_image: Option<Vec<[f32; 4]>>,
+    radiance_readback: Readback,
+    /// Readback requested for the next frame.
+    radiance_request: Option<RadianceReadback>,
+    /// Readback in flight, with the canvas size it was copied at.
+    radiance_copied: Option<(RadianceReadback, u32, u32)>,
+    world: ChunkedWorld,
+    /// Canvas resolution, shared by the layers and every intermediate texture.
     width: u32,
     height: u32,
 }
@@ -52,6 +161,7 @@ pub struct RenderPassManager {
 impl RenderPassManager {
     pub fn new(
         device: &Device,
+        queue: &Queue,
         config: &wgpu::SurfaceConfiguration,
         width: u32,
         height: u32,
@@ -64,13 +174,45 @@ impl RenderPassManager {
             TextureType::SceneTexture,
             1.0,
         );
+        // Transmitted color of translucent texels, black where the scene is opaque, and
+        // whether the texel is a mirror in alpha.
+        texture_manager.create_texture(
+            "MaterialTexture",
+            (width, height),
+            device,
+            TextureType::Standard,
+            1.0,
+        );
+        // Fog color and density composited from the fog layers.
+        texture_manager.create_texture(
+            "FogTexture",
+            (width, height),
+            device,
+            TextureType::Standard,
+            1.0,
+        );
+        // How high the surfaces reach in red, in world units.
+        texture_manager.create_texture(
+            "HeightTexture",
+            (width, height),
+            device,
+            TextureType::Standard,
+            1.0,
+        );
         let quad_render_pass = QuadVertexRenderPass::new(device);
+        let brush_pass = BrushPass::new(device, &texture_manager);
+        let flood_fill_pass = FloodFillPass::new(device, &texture_manager);
+        let layer_composite_pass = LayerCompositePass::new(device, &texture_manager);
+        let object_render_pass = ObjectRenderPass::new(device, queue, &texture_manager);
+        let lit_sprite_pass = LitSpritePass::new(device, queue, config);
+        let render_options = RenderOptions::default();
         let jfa_passes_manager = JfaPassesManager::new(
             device,
             width,
             height,
             &quad_render_pass,
             &mut texture_manager,
+            render_options.seed,
         );
         let show_pass = ShowRenderPass::new(device, config, &quad_render_pass);
         let distant_field_pass = DistantFieldPass::new(
@@ -82,79 +224,620 @@ impl RenderPassManager {
         );
         let radiance_passes_manager = RadianceCascadesPassesManager::new(
             device,
+            queue,
             width,
             height,
             &quad_render_pass,
             &mut texture_manager,
+            render_options.seed,
         );
 
         Self {
+            brush_pass,
+            flood_fill_pass,
+            layer_composite_pass,
+            object_render_pass,
+            lit_sprite_pass,
             quad_render_pass,
-            render_options: Default::default(),
+            seed: render_options.seed,
+            render_options,
             show_pass,
             distant_field_pass,
             texture_manager,
             radiance_passes_manager,
             jfa_passes_manager,
+            pass_cache: Default::default(),
+            composited_layers: None,
+            pending_bounces: 0,
+            jfa_partial: false,
+            object_regions: Vec::new(),
+            drawn_objects: Vec::new(),
+            reference_image: None,
+            radiance_readback: Readback::new("Radiance readback"),
+            radiance_request: None,
+            radiance_copied: None,
+            world: ChunkedWorld::new(device, width, height),
             width,
             height,
         }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32, device: &Device, queue: &Queue) {
+    /// Changes the canvas resolution. The world is kept, the canvas grows or shrinks
+    /// around `anchor`.
+    pub fn resize_canvas(
+        &mut self,
+        canvas: CanvasSettings,
+        device: &Device,
+        queue: &Queue,
+        layers: &LayerStack,
+    ) {
         puffin::profile_function!();
+        let CanvasSettings {
+            width,
+            height,
+            anchor,
+        } = canvas;
+        if (width, height) == (self.width, self.height) || width == 0 || height == 0 {
+            return;
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        // Queued stamps still target the old textures, so they are saved along.
+        self.apply_pending_stamps(&mut encoder, layers);
+        self.save_layers(device, &mut encoder, layers);
+
         self.texture_manager.resize(device, (width, height));
         self.jfa_passes_manager.resize(device, width, height);
         self.radiance_passes_manager.resize(width, height);
+
+        let offset = match anchor {
+            CanvasAnchor::TopLeft => IVec2::ZERO,
+            CanvasAnchor::Center => {
+                (IVec2::new(width as i32, height as i32)
+                    - IVec2::new(self.width as i32, self.height as i32))
+                    / 2
+            }
+        };
+        self.world.resize(width, height, offset);
+        for layer in layers.layers() {
+            let name = layer.texture_name();
+            if let Some(scene) = self
+                .texture_manager
+                .get_texture_mut(&name)
+                .and_then(ManagedTexture::scene_mut)
+            {
+                self.world.load(&mut encoder, &name, scene);
+            }
+        }
+        queue.submit(Some(encoder.finish()));
+
+        self.pass_cache.invalidate();
+        self.composited_layers = None;
+        self.object_regions.clear();
         self.width = width;
         self.height = height;
     }
 
-    pub fn render(&mut self, view: &TextureView, encoder: &mut CommandEncoder, device: &Device) {
+    pub fn canvas_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn texel_grid(&self) -> TexelGrid {
+        self.world.grid()
+    }
+
+    /// Streams the part of the world around `camera` onto the canvas, starting next frame.
+    pub fn follow_camera(&mut self, camera: &Camera) {
+        self.world.follow(camera, self.render_options.stream_margin);
+    }
+
+    pub fn canvas_transform(&self, camera: &Camera) -> CanvasTransform {
+        CanvasTransform {
+            offset: self.world.world_to_canvas(camera.view_top_left()),
+            scale: camera.world_per_pixel() * self.world.grid().texels_per_unit,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        view: &TextureView,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
+        history: &mut History,
+        layers: &mut LayerStack,
+        objects: &[SceneObject],
+        lit_sprites: &[LitSprite],
+        lights: &[Light],
+        camera: &Camera,
+    ) {
         puffin::profile_function!();
-        self.jfa_passes_manager.render(
-            &self.render_options.jfa_options,
+        if !self.render_options.skip_unchanged_passes {
+            self.pass_cache.invalidate();
+            self.composited_layers = None;
+        }
+        if self.render_options.seed != self.seed {
+            self.seed = self.render_options.seed;
+            self.jfa_passes_manager
+                .set_seed(device, self.seed, self.width, self.height);
+            self.radiance_passes_manager.set_seed(self.seed);
+            self.pass_cache.invalidate();
+        }
+        self.sync_layers(device, layers, history);
+        for layer in layers.layers() {
+            let name = layer.texture_name();
+            if let Some(scene) = self.layer_texture_mut(&name) {
+                history.prepare(device, encoder, &name, scene);
+            }
+        }
+        self.stream_world(device, encoder, history, layers);
+        self.apply_pending_stamps(encoder, layers);
+        if self.flood_fill_pass.render(
             encoder,
+            device,
+            queue,
             &mut self.texture_manager,
-            &self.quad_render_pass,
+            self.world.origin(),
             self.width,
             self.height,
+        ) {
+            history.commit(Tool::FloodFill.name());
+        }
+        history.apply(device, encoder, &mut self.texture_manager, &mut self.world);
+        let objects_changed = self.drawn_objects != objects;
+        if self.composite_layers(encoder, device, layers, objects_changed) {
+            self.render_objects(encoder, device, queue, objects);
+        }
+        let scene_generation = self.scene_generation();
+        let dirty_rects = self.take_scene_dirty_rects();
+        let scene_changed = self.pass_cache.scene_generation != scene_generation;
+        let jfa_options = self.render_options.jfa_options;
+        let jfa_options_changed = self.pass_cache.jfa_options != Some(jfa_options);
+        // Regional updates leave the field away from the strokes as it was, it is
+        // recomputed in full once the scene stops changing.
+        let settle_jfa = self.jfa_partial && !scene_changed;
+        let jfa_changed = scene_changed || jfa_options_changed || settle_jfa;
+        let lights: Vec<LightData> = lights
+            .iter()
+            .filter(|light| light.enabled)
+            .map(|light| light.canvas_data(&self.world))
+            .collect();
+        let radiance_inputs_changed = jfa_changed
+            || self.pass_cache.radiance_options != Some(self.render_options.radiance_options)
+            || self.pass_cache.lights.as_ref() != Some(&lights);
+        let fog = layers
+            .layers()
+            .iter()
+            .any(|layer| layer.fog && layer.visible);
+        let radiance_options = &self.render_options.radiance_options;
+        if radiance_inputs_changed && radiance_options.feeds_back(fog) {
+            self.pending_bounces = BOUNCE_FRAMES;
+        }
+        if radiance_inputs_changed {
+            self.radiance_passes_manager.restart_reference();
+        }
+        let radiance_changed = radiance_inputs_changed
+            || self.pending_bounces > 0
+            || self.radiance_passes_manager.converging(radiance_options);
+        self.pending_bounces = self.pending_bounces.saturating_sub(1);
+
+        if jfa_changed {
+            let full = DirtyRect::full(self.width, self.height);
+            let regions = if jfa_options_changed
+                || settle_jfa
+                || self.pass_cache.scene_generation.is_none()
+            {
+                None
+            } else {
+                jfa_options.update_regions(&dirty_rects, self.width, self.height)
+            };
+            self.jfa_partial = regions.is_some();
+            for region in regions.unwrap_or_else(|| vec![full]) {
+                self.jfa_passes_manager.render_region(
+                    &jfa_options,
+                    encoder,
+                    &mut self.texture_manager,
+                    &self.quad_render_pass,
+                    self.width,
+                    self.height,
+                    region,
+                );
+                self.distant_field_pass.render(
+                    encoder,
+                    device,
+                    &self.texture_manager,
+                    &self.quad_render_pass,
+                    region,
+                );
+            }
+        }
+        if radiance_changed {
+            self.radiance_passes_manager.render(
+                &self.render_options.radiance_options,
+                encoder,
+                device,
+                queue,
+                &mut self.texture_manager,
+                &self.quad_render_pass,
+                &lights,
+                fog,
+            );
+        }
+        self.pass_cache = PassCache {
+            scene_generation,
+            jfa_options: Some(self.render_options.jfa_options),
+            radiance_options: Some(self.render_options.radiance_options),
+            lights: Some(lights),
+        };
+        let transform = self.canvas_transform(camera);
+        if let Some(texture) = self.texture_manager.get_texture(&self.render_options.show) {
+            self.show_pass.render(
+                encoder,
+                texture.bind_group(),
+                view,
+                &self.quad_render_pass,
+                transform,
+                (self.width, self.height),
+            );
+        }
+        // Lit sprites are shaded with the harmonics, stale in the fragment modes.
+        if self.render_options.show == "RadianceCascades"
+            && self.radiance_passes_manager.has_harmonics()
+        {
+            self.lit_sprite_pass.render(
+                encoder,
+                device,
+                view,
+                &self.texture_manager,
+                &self.quad_render_pass,
+                camera,
+                self.world.grid(),
+                self.world.origin(),
+                (self.width, self.height),
+                lit_sprites,
+            );
+        }
+        self.copy_radiance(device, encoder);
+    }
+
+    pub fn get_options(&mut self) -> &mut RenderOptions {
+        &mut self.render_options
+    }
+
+    /// Lights placed in the world, in world units.
+    pub fn lights(&self) -> &[Light] {
+        self.world.lights()
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        self.world.lights_mut()
+    }
+
+    /// Render options and lights, to edit both at once.
+    pub fn options_and_lights(&mut self) -> (&mut RenderOptions, &mut Vec<Light>) {
+        (&mut self.render_options, self.world.lights_mut())
+    }
+
+    /// Frames the reference mode has averaged, `None` when another mode is selected.
+    pub fn reference_samples(&self) -> Option<u32> {
+        self.render_options
+            .radiance_options
+            .reference()
+            .map(|_| self.radiance_passes_manager.reference_samples())
+    }
+
+    /// Reads the radiance back once the next frame rendered it, the result comes out of
+    /// `take_radiance_readback` a few frames later.
+    pub fn read_radiance(&mut self, request: RadianceReadback) {
+        self.radiance_request = Some(request);
+    }
+
+    pub fn has_reference(&self) -> bool {
+        self.reference_image.is_some()
+    }
+
+    /// The radiance readback that finished since the last call, if any. Never waits.
+    pub fn take_radiance_readback(&mut self, device: &Device) -> Option<ReferenceReadback> {
+        let bytes = self.radiance_readback.take(device)?;
+        let (request, width, height) = self.radiance_copied.take()?;
+        let pixels: Vec<[f32; 4]> = bytemuck::pod_collect_to_vec(&bytes);
+        match request {
+            RadianceReadback::Save => {
+                self.reference_image = Some(pixels.clone());
+                Some(ReferenceReadback::Saved {
+                    width,
+                    height,
+                    pixels,
+                })
+            }
+            RadianceReadback::Compare => Some(ReferenceReadback::Compared(
+                self.reference_image
+                    .as_ref()
+                    .and_then(|reference| ImageError::compare(&pixels, reference)),
+            )),
+        }
+    }
+
+    fn copy_radiance(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        if !self.radiance_readback.is_idle() {
+            return;
+        }
+        let Some(request) = self.radiance_request.take() else {
+            return;
+        };
+        if let Some(texture) = self.texture_manager.get_texture("RadianceCascades") {
+            self.radiance_readback
+                .copy_texture(device, encoder, texture.texture());
+            self.radiance_copied = Some((request, self.width, self.height));
+        }
+    }
+
+    /// Seed of the noise on the CPU side, see `RenderOptions::seed`.
+    pub fn seed(&self) -> u32 {
+        self.render_options.seed
+    }
+
+    fn scene_generation(&self) -> Option<u64> {
+        self.texture_manager
+            .get_texture("SceneTexture")
+            .and_then(ManagedTexture::scene)
+            .map(SceneTexture::generation)
+    }
+
+    fn scene_texture_mut(&mut self) -> Option<&mut SceneTexture> {
+        self.layer_texture_mut("SceneTexture")
+    }
+
+    fn layer_texture_mut(&mut self, name: &str) -> Option<&mut SceneTexture> {
+        self.texture_manager
+            .get_texture_mut(name)
+            .and_then(ManagedTexture::scene_mut)
+    }
+
+    /// Creates textures for new layers and drops the ones of removed layers.
+    fn sync_layers(&mut self, device: &Device, layers: &mut LayerStack, history: &mut History) {
+        for name in layers.take_removed() {
+            self.texture_manager.remove_texture(&name);
+            history.forget(&name);
+            self.world.forget(&name);
+        }
+        for layer in layers.layers() {
+            let name = layer.texture_name();
+            if self.texture_manager.get_texture(&name).is_none() {
+                self.texture_manager.create_texture(
+                    &name,
+                    (self.width, self.height),
+                    device,
+                    TextureType::SceneTexture,
+                    1.0,
+                );
+            }
+        }
+    }
+
+    /// Saves this frame's edits to the world, then moves the canvas if the camera asked for it.
+    fn stream_world(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        history: &mut History,
+        layers: &LayerStack,
+    ) {
+        puffin::profile_function!();
+        self.save_layers(device, encoder, layers);
+        let origin = self.world.origin();
+        if self.world.take_move().is_none() {
+            return;
+        }
+        let world = &self.world;
+        history.shift(
+            device,
+            encoder,
+            &mut self.texture_manager,
+            origin,
+            |encoder, texture_manager| {
+                for layer in layers.layers() {
+                    let name = layer.texture_name();
+                    if let Some(scene) = texture_manager
+                        .get_texture_mut(&name)
+                        .and_then(ManagedTexture::scene_mut)
+                    {
+                        world.load(encoder, &name, scene);
+                    }
+                }
+            },
         );
-        self.distant_field_pass.render(
+    }
+
+    fn save_layers(&mut self, device: &Device, encoder: &mut CommandEncoder, layers: &LayerStack) {
+        for layer in layers.layers() {
+            let name = layer.texture_name();
+            if let Some(scene) = self
+                .texture_manager
+                .get_texture_mut(&name)
+                .and_then(ManagedTexture::scene_mut)
+            {
+                self.world.save(device, encoder, &name, scene);
+            }
+        }
+    }
+
+    /// Recomposites the regions painted in any layer, or everything when the layer
+    /// settings changed. Returns whether the objects have to be drawn again, which is
+    /// when they changed or anything was recomposited.
+    fn composite_layers(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        layers: &LayerStack,
+        objects_changed: bool,
+    ) -> bool {
+        let mut regions = Vec::new();
+        for layer in layers.layers() {
+            if let Some(texture) = self.layer_texture_mut(&layer.texture_name()) {
+                regions.extend(texture.take_dirty_rects());
+            }
+        }
+        if self.composited_layers.as_deref() != Some(layers.layers()) {
+            regions = vec![DirtyRect::full(self.width, self.height)];
+        }
+        let redraw_objects = objects_changed || !regions.is_empty();
+        if redraw_objects {
+            // Compositing again erases the objects drawn last frame.
+            regions.append(&mut self.object_regions);
+        }
+        DirtyRect::merge_overlapping(&mut regions);
+        for &region in &regions {
+            self.layer_composite_pass.render(
+                encoder,
+                device,
+                &self.texture_manager,
+                layers,
+                region,
+            );
+        }
+        if let Some(scene) = self.scene_texture_mut() {
+            for region in regions {
+                scene.mark_changed(region);
+            }
+        }
+        self.composited_layers = Some(layers.layers().to_vec());
+        redraw_objects
+    }
+
+    fn render_objects(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        queue: &Queue,
+        objects: &[SceneObject],
+    ) {
+        self.object_render_pass.render(
             encoder,
             device,
+            queue,
             &self.texture_manager,
             &self.quad_render_pass,
+            &self.world.canvas_camera(),
+            objects,
         );
-        self.radiance_passes_manager.render(
-            &self.render_options.radiance_options,
-            encoder,
-            &mut self.texture_manager,
-            &self.quad_render_pass,
-        );
-        if let Some(texture) = self.texture_manager.get_texture(&self.render_options.show) {
-            self.show_pass
-                .render(encoder, texture.bind_group(), view, &self.quad_render_pass);
+        self.object_regions = objects
+            .iter()
+            .filter_map(|object| {
+                let (min, max) = object.bounds();
+                self.world.canvas_rect(min, max)
+            })
+            .collect();
+        self.drawn_objects = objects.to_vec();
+        let regions = self.object_regions.clone();
+        if let Some(scene) = self.scene_texture_mut() {
+            for region in regions {
+                scene.mark_changed(region);
+            }
         }
     }
 
-    pub fn get_options(&mut self) -> &mut RenderOptions {
-        &mut self.render_options
+    fn take_scene_dirty_rects(&mut self) -> Vec<DirtyRect> {
+        self.scene_texture_mut()
+            .map(SceneTexture::take_dirty_rects)
+            .unwrap_or_default()
+    }
+
+    fn apply_pending_stamps(&mut self, encoder: &mut CommandEncoder, layers: &LayerStack) {
+        for layer in layers.layers() {
+            let Some(scene) = self
+                .texture_manager
+                .get_texture_mut(&layer.texture_name())
+                .and_then(ManagedTexture::scene_mut)
+            else {
+                continue;
+            };
+            let stamps = scene.take_pending_stamps();
+            self.brush_pass
+                .render(encoder, scene, &stamps, self.width, self.height);
+        }
     }
 
-    pub fn paint(
+    /// The active layer, unless it is locked or its texture does not exist yet.
+    fn paint_target(&mut self, layers: &LayerStack) -> Option<&mut SceneTexture> {
+        let layer = layers.active_layer();
+        if layer.locked {
+            return None;
+        }
+        self.layer_texture_mut(&layer.texture_name())
+    }
+
+    /// Uploads an RGBA8 sprite for `ObjectShape::Sprite`.
+    pub fn add_sprite(
         &mut self,
-        pos: Vec2,
-        color: [f32; 4],
-        brush_radius: u32,
+        device: &Device,
+        queue: &Queue,
         width: u32,
         height: u32,
+        rgba: &[u8],
+    ) -> SpriteId {
+        self.object_render_pass.add_sprite(
+            device,
+            queue,
+            &self.texture_manager,
+            width,
+            height,
+            rgba,
+        )
+    }
+
+    /// Uploads an RGBA8 sprite and its normal map for `LitSprite`.
+    pub fn add_lit_sprite(
+        &mut self,
+        device: &Device,
         queue: &Queue,
-    ) {
-        if let Some(ManagedTexture::SceneTexture(texture)) =
-            self.texture_manager.get_texture_mut("SceneTexture")
-        {
-            texture.paint(pos, color, brush_radius, width, height, queue);
+        width: u32,
+        height: u32,
+        albedo: &[u8],
+        normals: &[u8],
+    ) -> LitSpriteId {
+        self.lit_sprite_pass
+            .add_sprite(device, queue, width, height, albedo, normals)
+    }
+
+    /// Colors seen by rays leaving the canvas in the `SkyRing` boundary mode.
+    pub fn set_environment_ring(&mut self, queue: &Queue, colors: &[[f32; 4]]) {
+        self.radiance_passes_manager
+            .set_environment_ring(queue, colors);
+        self.pass_cache.radiance_options = None;
+    }
+
+    /// Paints a stamp given in world texels.
+    pub fn paint(&mut self, layers: &LayerStack, stamp: BrushStamp) {
+        // Stamps are applied after the canvas reached its target.
+        let offset = -self.world.target().as_vec2();
+        if let Some(scene) = self.paint_target(layers) {
+            scene.paint(stamp.translated(offset));
+        }
+    }
+
+    /// Starts flood filling the active layer from `seed`, given in world texels. Only the
+    /// part of the world on the canvas is filled. The fill takes a few frames, it is added
+    /// to `history` once painted.
+    pub fn flood_fill(&mut self, layers: &LayerStack, seed: Vec2, color: [f32; 4], tolerance: f32) {
+        let layer = layers.active_layer();
+        let seed = seed - self.world.origin().as_vec2();
+        if layer.locked || seed.cmplt(Vec2::ZERO).any() {
+            return;
         }
+        self.flood_fill_pass.start(
+            layer.texture_name(),
+            self.world.origin(),
+            (seed.x as u32, seed.y as u32),
+            color,
+            tolerance,
+        );
+    }
+
+    /// Starts the readbacks recorded this frame, call once it was submitted.
+    pub fn submitted(&mut self) {
+        self.flood_fill_pass.submitted();
+        self.radiance_readback.submitted();
     }
 }
@@ -2,7 +2,10 @@ use wgpu::{
     CommandEncoder, Device,
 };
 
-use crate::{render_passes::quad_vertex::QuadVertexRenderPass, texture_manager::TextureManager};
+use crate::{
+    render_passes::quad_vertex::QuadVertexRenderPass,
+    texture_manager::{TextureManager, dirty_rect::DirtyRect},
+};
 use crate::{texture_manager::textures::EngineTexture, vertex_state_for_quad};
 
 pub struct SeedRenderPass {
@@ -64,6 +67,7 @@ impl SeedRenderPass {
         encoder: &mut CommandEncoder,
         texture_manager: &TextureManager,
         quad_render_pass: &QuadVertexRenderPass,
+        region: DirtyRect,
     ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Seed  Render Pass"),
@@ -71,7 +75,8 @@ impl SeedRenderPass {
                 view: texture_manager.get_texture("JfaTexture").unwrap().view(),
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    // Texels outside `region` keep the previous JFA result.
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Store,
                 },
                 depth_slice: Default::default(),
@@ -82,6 +87,7 @@ impl SeedRenderPass {
         });
 
         render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_scissor_rect(region.min_x, region.min_y, region.width(), region.height());
         render_pass.set_bind_group(
             0,
             texture_manager
@@ -0,0 +1,111 @@
+@group(0) @binding(0) var scene_texture: texture_storage_2d<rgba32float, read_write>;
+
+struct BrushStamp {
+    center: vec2<f32>,
+    radius: f32,
+    hardness: f32,
+    color: vec4<f32>,
+    opacity: f32,
+    mode: u32,
+    shape: u32,
+    outline: f32,
+    extent: vec2<f32>,
+    _pad: vec2<f32>,
+};
+
+struct PushConstants {
+    stamp: BrushStamp,
+    region_min: vec2<i32>,
+    region_max: vec2<i32>,
+};
+
+var<push_constant> constants: PushConstants;
+
+const MODE_MIX: u32 = 0u;
+const MODE_ERASE: u32 = 1u;
+
+const SHAPE_CIRCLE: u32 = 0u;
+const SHAPE_LINE: u32 = 1u;
+const SHAPE_RECT: u32 = 2u;
+const SHAPE_ELLIPSE: u32 = 3u;
+
+fn sdSegment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / max(dot(ba, ba), 1e-6), 0.0, 1.0);
+    return length(pa - ba * h);
+}
+
+fn sdBox(p: vec2<f32>, half_size: vec2<f32>) -> f32 {
+    let d = abs(p) - half_size;
+    return length(max(d, vec2(0.0))) + min(max(d.x, d.y), 0.0);
+}
+
+// Approximate, but exact on the boundary which is all the coverage needs.
+fn sdEllipse(p: vec2<f32>, radii: vec2<f32>) -> f32 {
+    let r = max(radii, vec2(1e-3));
+    let k0 = length(p / r);
+    let k1 = length(p / (r * r));
+    if k1 == 0.0 {
+        return -min(r.x, r.y);
+    }
+    return k0 * (k0 - 1.0) / k1;
+}
+
+// Signed distance to the stamp edge, negative inside.
+fn stampDistance(p: vec2<f32>, stamp: BrushStamp) -> f32 {
+    var d: f32;
+    switch stamp.shape {
+        case SHAPE_LINE: {
+            d = sdSegment(p, stamp.center, stamp.extent) - stamp.radius;
+        }
+        case SHAPE_RECT: {
+            d = sdBox(p - stamp.center, stamp.extent) - stamp.radius;
+        }
+        case SHAPE_ELLIPSE: {
+            d = sdEllipse(p - stamp.center, stamp.extent) - stamp.radius;
+        }
+        default: {
+            d = distance(p, stamp.center) - stamp.radius;
+        }
+    }
+    if stamp.outline > 0.0 {
+        d = abs(d) - stamp.outline * 0.5;
+    }
+    return d;
+}
+
+@compute @workgroup_size(16, 16)
+fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pixelCoord = vec2<i32>(id.xy) + constants.region_min;
+    if any(pixelCoord >= constants.region_max) {
+        return;
+    }
+    let stamp = constants.stamp;
+
+    let dist = stampDistance(vec2<f32>(pixelCoord), stamp);
+    // At least one texel of falloff, so hard brushes are still antialiased.
+    let falloff = max(stamp.radius * (1.0 - stamp.hardness), 1.0);
+    let coverage = clamp(-dist / falloff + 0.5, 0.0, 1.0);
+    let alpha = coverage * stamp.opacity;
+    if alpha <= 0.0 {
+        return;
+    }
+
+    let old = textureLoad(scene_texture, pixelCoord);
+    var new_color = old;
+    switch stamp.mode {
+        case MODE_MIX: {
+            new_color = mix(old, stamp.color, alpha);
+        }
+        case MODE_ERASE: {
+            new_color = old * (1.0 - alpha);
+            // Any alpha left would still seed the distance field.
+            if new_color.a < 1.0 / 255.0 {
+                new_color = vec4(0.0);
+            }
+        }
+        default: {}
+    }
+    textureStore(scene_texture, pixelCoord, new_color);
+}
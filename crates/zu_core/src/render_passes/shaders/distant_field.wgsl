@@ -10,5 +10,6 @@ struct VertexOutput {
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     var nearestSeed: vec2<f32> = textureSample(jfa_texture, my_sampler, input.uv).xy;
     var distance: f32 = clamp(distance(input.uv, nearestSeed), 0.0, 1.0);
-    return vec4(vec3(distance), 1.0);
+    // The nearest surface is kept for the height lookup of the radiance pass.
+    return vec4(distance, nearestSeed, 1.0);
 }
@@ -0,0 +1,116 @@
+@group(0) @binding(0) var scene_texture: texture_storage_2d<rgba32float, read_write>;
+@group(1) @binding(0) var mask_texture: texture_storage_2d<r32uint, read_write>;
+@group(1) @binding(1) var<storage, read_write> status: FillStatus;
+
+struct FillStatus {
+    // Set by every spread pass that reached a new texel.
+    changed: atomic<u32>,
+    min_x: atomic<u32>,
+    min_y: atomic<u32>,
+    max_x: atomic<u32>,
+    max_y: atomic<u32>,
+    seed_color: vec4<f32>,
+};
+
+struct PushConstants {
+    color: vec4<f32>,
+    seed: vec2<u32>,
+    tolerance: f32,
+    _pad: f32,
+};
+
+var<push_constant> constants: PushConstants;
+
+const TILE_SIZE: u32 = 16u;
+// Steps taken inside a tile per spread pass, enough to cross it twice.
+const TILE_STEPS: u32 = 32u;
+
+var<workgroup> tile: array<u32, 256>;
+
+fn matchesSeed(color: vec4<f32>) -> bool {
+    return all(abs(color - status.seed_color) <= vec4(constants.tolerance));
+}
+
+fn isFilled(pixel: vec2<i32>) -> bool {
+    let size = vec2<i32>(textureDimensions(mask_texture));
+    if any(pixel < vec2(0)) || any(pixel >= size) {
+        return false;
+    }
+    return textureLoad(mask_texture, pixel).r != 0u;
+}
+
+fn tileFilled(local: vec2<i32>) -> bool {
+    if any(local < vec2(0)) || any(local >= vec2(i32(TILE_SIZE))) {
+        return false;
+    }
+    return tile[u32(local.y) * TILE_SIZE + u32(local.x)] != 0u;
+}
+
+fn hasFilledNeighbour(pixel: vec2<i32>) -> bool {
+    return isFilled(pixel + vec2(1, 0)) || isFilled(pixel - vec2(1, 0))
+        || isFilled(pixel + vec2(0, 1)) || isFilled(pixel - vec2(0, 1));
+}
+
+// Clears the mask and marks the seed texel.
+@compute @workgroup_size(16, 16)
+fn init_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(mask_texture)) {
+        return;
+    }
+    let is_seed = all(id.xy == constants.seed);
+    textureStore(mask_texture, id.xy, vec4(u32(is_seed)));
+    if is_seed {
+        status.seed_color = textureLoad(scene_texture, id.xy);
+        atomicStore(&status.min_x, id.x);
+        atomicStore(&status.min_y, id.y);
+        atomicStore(&status.max_x, id.x);
+        atomicStore(&status.max_y, id.y);
+    }
+}
+
+// Grows the mask over texels matching the seed color, 4-connected. The fill spreads
+// through a whole tile per pass and into the neighbouring tiles from there.
+@compute @workgroup_size(16, 16)
+fn spread_main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let pixel = vec2<i32>(id.xy);
+    let local = vec2<i32>(local_id.xy);
+    var filled = false;
+    var open = false;
+    if all(id.xy < textureDimensions(mask_texture)) {
+        filled = isFilled(pixel);
+        open = !filled && matchesSeed(textureLoad(scene_texture, pixel));
+        filled = filled || (open && hasFilledNeighbour(pixel));
+    }
+    // No early returns, every invocation has to reach the barriers.
+    for (var step = 0u; step < TILE_STEPS; step++) {
+        tile[local_id.y * TILE_SIZE + local_id.x] = u32(filled);
+        workgroupBarrier();
+        if open && !filled {
+            filled = tileFilled(local + vec2(1, 0)) || tileFilled(local - vec2(1, 0))
+                || tileFilled(local + vec2(0, 1)) || tileFilled(local - vec2(0, 1));
+        }
+        workgroupBarrier();
+    }
+    if open && filled {
+        textureStore(mask_texture, pixel, vec4(1u));
+        atomicStore(&status.changed, 1u);
+        atomicMin(&status.min_x, id.x);
+        atomicMin(&status.min_y, id.y);
+        atomicMax(&status.max_x, id.x);
+        atomicMax(&status.max_y, id.y);
+    }
+}
+
+// Paints the filled texels.
+@compute @workgroup_size(16, 16)
+fn fill_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(mask_texture)) {
+        return;
+    }
+    if textureLoad(mask_texture, id.xy).r != 0u {
+        textureStore(scene_texture, id.xy, constants.color);
+    }
+}
@@ -0,0 +1,108 @@
+@group(0) @binding(0) var scene_texture: texture_storage_2d<rgba32float, read_write>;
+@group(1) @binding(0) var layer_texture: texture_2d<f32>;
+@group(2) @binding(0) var material_texture: texture_storage_2d<rgba32float, read_write>;
+@group(3) @binding(0) var fog_texture: texture_storage_2d<rgba32float, read_write>;
+// Height of the surfaces in red, in world units.
+@group(3) @binding(1) var height_texture: texture_storage_2d<rgba32float, read_write>;
+
+// `FULL_HEIGHT` in `layers.rs`, no light shines over surfaces this tall.
+const FULL_HEIGHT: f32 = 1.0e6;
+
+struct PushConstants {
+    region_min: vec2<i32>,
+    region_max: vec2<i32>,
+    opacity: f32,
+    blend_mode: u32,
+    clear: u32,
+    transmission: f32,
+    reflective: u32,
+    fog: u32,
+    height: f32,
+    _padding: u32,
+};
+
+var<push_constant> constants: PushConstants;
+
+const BLEND_NORMAL: u32 = 0u;
+const BLEND_ADD: u32 = 1u;
+const BLEND_MULTIPLY: u32 = 2u;
+
+@compute @workgroup_size(16, 16)
+fn fs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pixelCoord = vec2<i32>(id.xy) + constants.region_min;
+    if any(pixelCoord >= constants.region_max) {
+        return;
+    }
+
+    var dst = vec4(0.0);
+    var dst_material = vec4(0.0);
+    var dst_fog = vec4(0.0);
+    var dst_height = vec4(0.0);
+    if constants.clear == 0u {
+        dst = textureLoad(scene_texture, pixelCoord);
+        dst_material = textureLoad(material_texture, pixelCoord);
+        dst_fog = textureLoad(fog_texture, pixelCoord);
+        dst_height = textureLoad(height_texture, pixelCoord);
+    }
+    let layer = textureLoad(layer_texture, pixelCoord, 0);
+    let alpha = layer.a * constants.opacity;
+
+    if constants.fog != 0u {
+        // Densities add up, colors mix by how much each layer contributes.
+        let density = dst_fog.a + alpha;
+        var fog = vec4(0.0);
+        if density > 0.0 {
+            fog = vec4((dst_fog.rgb * dst_fog.a + layer.rgb * alpha) / density, density);
+        }
+        textureStore(scene_texture, pixelCoord, dst);
+        textureStore(material_texture, pixelCoord, dst_material);
+        textureStore(fog_texture, pixelCoord, fog);
+        textureStore(height_texture, pixelCoord, dst_height);
+        return;
+    }
+    // Translucent texels tint the light passing through and only emit the rest.
+    let src = vec4(layer.rgb * (1.0 - constants.transmission), layer.a);
+    // Mirrors keep their color in the scene, the raymarcher reads it as their tint.
+    let src_material = vec4(layer.rgb * constants.transmission, f32(constants.reflective));
+
+    var result = dst;
+    var material = dst_material;
+    switch constants.blend_mode {
+        case BLEND_ADD: {
+            result = vec4(dst.rgb + src.rgb * alpha, max(dst.a, alpha));
+            material = over(src_material, alpha, dst_material, dst.a);
+        }
+        case BLEND_MULTIPLY: {
+            result = vec4(dst.rgb * mix(vec3(1.0), src.rgb, alpha), dst.a);
+        }
+        default: {
+            // Straight alpha "over".
+            let out_alpha = alpha + dst.a * (1.0 - alpha);
+            if out_alpha > 0.0 {
+                let rgb = (src.rgb * alpha + dst.rgb * dst.a * (1.0 - alpha)) / out_alpha;
+                result = vec4(rgb, out_alpha);
+            } else {
+                result = vec4(0.0);
+            }
+            material = over(src_material, alpha, dst_material, dst.a);
+        }
+    }
+    // Stacked surfaces reach as high as the tallest of them.
+    var height = dst_height;
+    if constants.blend_mode != BLEND_MULTIPLY && alpha > 0.1 {
+        let layer_height = clamp(constants.height, 0.0, FULL_HEIGHT);
+        height = vec4(max(dst_height.r, layer_height), 0.0, 0.0, 0.0);
+    }
+    textureStore(scene_texture, pixelCoord, result);
+    textureStore(material_texture, pixelCoord, material);
+    textureStore(fog_texture, pixelCoord, dst_fog);
+    textureStore(height_texture, pixelCoord, height);
+}
+
+fn over(src: vec4<f32>, src_alpha: f32, dst: vec4<f32>, dst_alpha: f32) -> vec4<f32> {
+    let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+    if out_alpha <= 0.0 {
+        return vec4(0.0);
+    }
+    return (src * src_alpha + dst * dst_alpha * (1.0 - src_alpha)) / out_alpha;
+}
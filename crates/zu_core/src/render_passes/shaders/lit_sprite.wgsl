@@ -0,0 +1,80 @@
+@group(0) @binding(0) var sprite_sampler: sampler;
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+@group(0) @binding(2) var normal_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var radiance_sampler: sampler;
+@group(1) @binding(1) var radiance_texture: texture_2d<f32>;
+@group(1) @binding(2) var harmonics_x: texture_2d<f32>;
+@group(1) @binding(3) var harmonics_y: texture_2d<f32>;
+
+struct PushConstants {
+    view_proj: mat4x4<f32>,
+    canvas_origin: vec2<f32>,
+    canvas_size: vec2<f32>,
+    texels_per_unit: f32,
+    _padding: vec3<f32>,
+};
+
+var<push_constant> constants: PushConstants;
+
+const HALF_PI: f32 = 1.57079633;
+
+struct VertexInput {
+    @location(0) vert_position: vec2<f32>,
+    @location(1) position: vec2<f32>,
+    @location(2) scale: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) rotation: f32,
+    @location(5) normal_strength: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) local: vec2<f32>,
+    @location(2) world: vec2<f32>,
+    @location(3) @interpolate(flat) rotation: f32,
+    @location(4) @interpolate(flat) normal_strength: f32,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let local = in.vert_position * in.scale;
+    let c = cos(in.rotation);
+    let s = sin(in.rotation);
+    let world_pos = vec2(c * local.x - s * local.y, s * local.x + c * local.y) + in.position;
+    out.position = constants.view_proj * vec4<f32>(world_pos, 0.0, 1.0);
+    out.color = in.color;
+    out.local = in.vert_position;
+    out.world = world_pos;
+    out.rotation = in.rotation;
+    out.normal_strength = in.normal_strength;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Texture rows go down, local y goes up.
+    let sprite_uv = vec2(in.local.x + 0.5, 0.5 - in.local.y);
+    let albedo = in.color * textureSample(albedo_texture, sprite_sampler, sprite_uv);
+    if albedo.a <= 0.0 {
+        discard;
+    }
+    let normal = textureSample(normal_texture, sprite_sampler, sprite_uv).xy * 2.0 - 1.0;
+    // Into world space with the sprite, then into canvas texels, whose y goes down.
+    let c = cos(in.rotation);
+    let s = sin(in.rotation);
+    let world_normal = vec2(c * normal.x - s * normal.y, s * normal.x + c * normal.y);
+    let canvas_normal = vec2(world_normal.x, -world_normal.y) * in.normal_strength;
+
+    let texel = vec2(in.world.x, -in.world.y) * constants.texels_per_unit - constants.canvas_origin;
+    let uv = texel / constants.canvas_size;
+    let average = textureSample(radiance_texture, radiance_sampler, uv).rgb;
+    let x = textureSample(harmonics_x, radiance_sampler, uv).rgb;
+    let y = textureSample(harmonics_y, radiance_sampler, uv).rgb;
+    // Lambert over the circle of incoming directions: a normal facing the viewer gets the
+    // average, tilting it towards the light brightens it.
+    let light = max(average + HALF_PI * (canvas_normal.x * x + canvas_normal.y * y), vec3(0.0));
+    return vec4(albedo.rgb * light, albedo.a);
+}
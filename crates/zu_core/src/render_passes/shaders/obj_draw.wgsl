@@ -1,12 +1,3 @@
-struct FragmentInput {
-    @location(0) frag_color: vec4<f32>,
-};
-
-@fragment
-fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
-    return in.frag_color;
-}
-
 struct Camera {
     proj_mat: mat4x4<f32>,
     cam_pos: vec2<f32>,
@@ -14,23 +5,82 @@ struct Camera {
 
 @group(0) @binding(0) var<uniform> camera: Camera;
 
+@group(1) @binding(0) var sprite_sampler: sampler;
+@group(1) @binding(1) var sprite_texture: texture_2d<f32>;
+
+const SHAPE_CIRCLE: u32 = 1u;
+const SHAPE_SPRITE: u32 = 2u;
+// `FULL_HEIGHT` in `layers.rs`, no light shines over surfaces this tall.
+const FULL_HEIGHT: f32 = 1.0e6;
+
 struct VertexInput {
     @location(0) vert_position: vec2<f32>,
     @location(1) position: vec2<f32>,
     @location(2) scale: vec2<f32>,
     @location(3) color: vec4<f32>,
+    @location(4) rotation: f32,
+    @location(5) shape: u32,
+    @location(6) emission: f32,
+    @location(7) transmission: f32,
+    @location(8) reflectivity: f32,
+    @location(9) height: f32,
 };
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) frag_color: vec4<f32>,
+    @location(1) local: vec2<f32>,
+    @location(2) @interpolate(flat) shape: u32,
+    @location(3) @interpolate(flat) emission: f32,
+    @location(4) @interpolate(flat) transmission: f32,
+    @location(5) @interpolate(flat) reflectivity: f32,
+    @location(6) @interpolate(flat) height: f32,
+};
+
+struct FragmentOutput {
+    @location(0) scene: vec4<f32>,
+    @location(1) material: vec4<f32>,
+    @location(2) height: vec4<f32>,
 };
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    let world_pos = (in.vert_position * in.scale) + in.position - camera.cam_pos;
-    out.position = vec4<f32>(world_pos, 0.0, 1.0) * camera.proj_mat;
+    let local = in.vert_position * in.scale;
+    let c = cos(in.rotation);
+    let s = sin(in.rotation);
+    let world_pos = vec2(c * local.x - s * local.y, s * local.x + c * local.y) + in.position;
+    out.position = camera.proj_mat * vec4<f32>(world_pos, 0.0, 1.0);
     out.frag_color = in.color;
+    out.local = in.vert_position;
+    out.shape = in.shape;
+    out.emission = in.emission;
+    out.transmission = in.transmission;
+    out.reflectivity = in.reflectivity;
+    out.height = in.height;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var color = in.frag_color;
+    if in.shape == SHAPE_CIRCLE && length(in.local) > 0.5 {
+        discard;
+    }
+    if in.shape == SHAPE_SPRITE {
+        // Texture rows go down, local y goes up.
+        let uv = vec2(in.local.x + 0.5, 0.5 - in.local.y);
+        color *= textureSampleLevel(sprite_texture, sprite_sampler, uv, 0.0);
+    }
+    // The scene has no blending, transparent texels leave what is below.
+    if color.a <= 0.0 {
+        discard;
+    }
+    var out: FragmentOutput;
+    // Mirrors keep their color in the scene, the raymarcher reads it as their tint.
+    let emitted = select(color.rgb * in.emission, color.rgb, in.reflectivity > 0.0);
+    out.scene = vec4(emitted, color.a);
+    out.material = vec4(color.rgb * in.transmission, in.reflectivity);
+    out.height = vec4(clamp(in.height, 0.0, FULL_HEIGHT), 0.0, 0.0, 0.0);
     return out;
 }
@@ -9,5 +9,6 @@ struct VertexOutput {
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     var alpha = textureSample(input_texture, my_sampler, input.uv).a;
-    if alpha > 0.0 {return vec4(input.uv * alpha, 0.0, 1.0);} else {return vec4(0.0, 0.0, 0.0, 0.0);}
+    // Seeds point at their own texel, the JFA compute pass looks them up in the scene.
+    if alpha > 0.0 {return vec4(input.uv, 0.0, 1.0);} else {return vec4(0.0, 0.0, 0.0, 0.0);}
 }
@@ -6,7 +6,24 @@ struct VertexOutput {
     @location(0) uv: vec2<f32>,
 };
 
+struct PushConstants {
+    offset: vec2<f32>,
+    canvas_size: vec2<f32>,
+    scale: f32,
+    _padding0: f32,
+    _padding1: vec2<f32>,
+};
+
+var<push_constant> constants: PushConstants;
+
+const BACKGROUND: vec4<f32> = vec4(0.05, 0.05, 0.05, 1.0);
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(input_texture, my_sampler, input.uv);
+    // clip_pos holds framebuffer coordinates in the fragment stage.
+    let canvas_pos = constants.offset + input.clip_pos.xy * constants.scale;
+    if any(canvas_pos < vec2(0.0)) || any(canvas_pos >= constants.canvas_size) {
+        return BACKGROUND;
+    }
+    return textureSample(input_texture, my_sampler, canvas_pos / constants.canvas_size);
 }
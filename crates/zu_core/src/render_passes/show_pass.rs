@@ -1,11 +1,40 @@
+use bytemuck::{Pod, Zeroable, bytes_of};
+use glam::Vec2;
 use wgpu::{
     BindGroup,
-    CommandEncoder, Device, TextureView,
+    CommandEncoder, Device, PushConstantRange, ShaderStages, TextureView,
 };
 
 use crate::render_passes::quad_vertex::QuadVertexRenderPass;
 use crate::vertex_state_for_quad;
 
+/// Maps viewport pixels to canvas texels: `canvas = offset + viewport * scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasTransform {
+    pub offset: Vec2,
+    /// Canvas texels per viewport pixel.
+    pub scale: f32,
+}
+
+impl Default for CanvasTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+struct ShowConstants {
+    offset: [f32; 2],
+    canvas_size: [f32; 2],
+    scale: f32,
+    _padding0: f32,
+    _padding1: [f32; 2],
+}
+
 pub struct ShowRenderPass {
     render_pipeline: wgpu::RenderPipeline,
     sampler: wgpu::Sampler,
@@ -57,7 +86,10 @@ impl ShowRenderPass {
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("show Pipeline Layout"),
             bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::FRAGMENT,
+                range: 0..std::mem::size_of::<ShowConstants>() as u32,
+            }],
         });
 
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -106,6 +138,8 @@ impl ShowRenderPass {
         input_texture: &BindGroup,
         output_view: &TextureView,
         quad_render_pass: &QuadVertexRenderPass,
+        transform: CanvasTransform,
+        canvas_size: (u32, u32),
     ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("show  Render Pass"),
@@ -125,6 +159,17 @@ impl ShowRenderPass {
 
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, input_texture, &[]);
+        render_pass.set_push_constants(
+            ShaderStages::FRAGMENT,
+            0,
+            bytes_of(&ShowConstants {
+                offset: transform.offset.into(),
+                canvas_size: [canvas_size.0 as f32, canvas_size.1 as f32],
+                scale: transform.scale,
+                _padding0: 0.0,
+                _padding1: [0.0; 2],
+            }),
+        );
         quad_render_pass.render(&mut render_pass);
     }
 }
@@ -0,0 +1,81 @@
+/// Texel rectangle, `min` inclusive and `max` exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl DirtyRect {
+    pub fn new(min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> Self {
+        Self {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    pub fn full(width: u32, height: u32) -> Self {
+        Self::new(0, 0, width, height)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.max_x.saturating_sub(self.min_x)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.max_y.saturating_sub(self.min_y)
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width() as u64 * self.height() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn union(&self, other: &DirtyRect) -> DirtyRect {
+        DirtyRect::new(
+            self.min_x.min(other.min_x),
+            self.min_y.min(other.min_y),
+            self.max_x.max(other.max_x),
+            self.max_y.max(other.max_y),
+        )
+    }
+
+    pub fn intersects(&self, other: &DirtyRect) -> bool {
+        self.min_x < other.max_x
+            && other.min_x < self.max_x
+            && self.min_y < other.max_y
+            && other.min_y < self.max_y
+    }
+
+    /// Grows the rect by `by` texels on every side, clamped to `width`x`height`.
+    pub fn expanded(&self, by: u32, width: u32, height: u32) -> DirtyRect {
+        DirtyRect::new(
+            self.min_x.saturating_sub(by),
+            self.min_y.saturating_sub(by),
+            (self.max_x + by).min(width),
+            (self.max_y + by).min(height),
+        )
+    }
+
+    /// Unions overlapping rects until all of them are disjoint.
+    pub fn merge_overlapping(rects: &mut Vec<DirtyRect>) {
+        let mut i = 0;
+        while i < rects.len() {
+            match ((i + 1)..rects.len()).find(|&j| rects[i].intersects(&rects[j])) {
+                Some(j) => {
+                    let other = rects.swap_remove(j);
+                    rects[i] = rects[i].union(&other);
+                    // The grown rect may now touch one that was already checked.
+                    i = 0;
+                }
+                None => i += 1,
+            }
+        }
+    }
+}
@@ -1,11 +1,16 @@
+pub mod dirty_rect;
+pub mod readback;
 pub mod textures;
 
 use indexmap::IndexMap;
 use wgpu::{
-    BindGroupLayout, Device, Sampler, TextureFormat,
+    BindGroupLayout, CommandEncoder, Device, Sampler, TextureFormat,
 };
 
-use crate::texture_manager::textures::{EngineTexture, ManagedTexture, TextureType};
+use crate::texture_manager::{
+    dirty_rect::DirtyRect,
+    textures::{EngineTexture, ManagedTexture, TextureType},
+};
 
 pub struct BindGroupLayouts {
     compute_texture: BindGroupLayout,
@@ -73,7 +78,6 @@ pub struct TextureManager {
     textures: IndexMap<String, ManagedTexture>,
     bind_group_layouts: BindGroupLayouts,
     sampler: Sampler,
-    num: usize,
 }
 
 impl TextureManager {
@@ -90,7 +94,6 @@ impl TextureManager {
                 mipmap_filter: wgpu::FilterMode::Nearest,
                 ..Default::default()
             }),
-            num: 0,
         }
     }
 
@@ -102,7 +105,7 @@ impl TextureManager {
         texture_type: TextureType,
         resolution_scale: f32,
     ) -> usize {
-        self.textures.insert(
+        let (index, _) = self.textures.insert_full(
             name.to_string(),
             ManagedTexture::new(
                 name,
@@ -114,8 +117,12 @@ impl TextureManager {
                 texture_type,
             ),
         );
-        self.num += 1;
-        self.num - 1
+        index
+    }
+
+    /// Removes a texture, indices of the textures created before it stay valid.
+    pub fn remove_texture(&mut self, name: &str) -> Option<ManagedTexture> {
+        self.textures.shift_remove(name)
     }
 
     pub fn get_texture(&self, name: &str) -> Option<&ManagedTexture> {
@@ -147,6 +154,42 @@ impl TextureManager {
         }
     }
 
+    pub fn copy_region(
+        &self,
+        encoder: &mut CommandEncoder,
+        src: &str,
+        dst: &str,
+        region: DirtyRect,
+    ) {
+        let (Some(src), Some(dst)) = (self.get_texture(src), self.get_texture(dst)) else {
+            return;
+        };
+        let origin = wgpu::Origin3d {
+            x: region.min_x,
+            y: region.min_y,
+            z: 0,
+        };
+        encoder.copy_texture_to_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: src.texture(),
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyTextureInfo {
+                texture: dst.texture(),
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: region.width(),
+                height: region.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
     pub fn get_bind_group_layout(&self) -> &BindGroupLayout {
         &self.bind_group_layouts.texture
     }
@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex};
+
+use wgpu::{Buffer, BufferAsyncError, CommandEncoder, Device, Texture};
+
+const TEXEL_SIZE: u32 = std::mem::size_of::<[f32; 4]>() as u32;
+
+type MapResult = Arc<Mutex<Option<Result<(), BufferAsyncError>>>>;
+
+enum ReadbackState {
+    Idle,
+    /// The copy is recorded, mapping starts once its frame is submitted.
+    Copied,
+    Mapping(MapResult),
+}
+
+/// Reads GPU data back without blocking a frame. The copy is recorded into a frame, the
+/// buffer is mapped once that frame was submitted and the data is taken in a later one.
+pub struct Readback {
+    label: &'static str,
+    buffer: Option<Buffer>,
+    /// Width and padded row pitch of a texture copy, `None` for buffer copies.
+    rows: Option<(u32, u32)>,
+    state: ReadbackState,
+}
+
+impl Readback {
+    pub fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            buffer: None,
+            rows: None,
+            state: ReadbackState::Idle,
+        }
+    }
+
+    /// No copy is in flight, a new one can be recorded.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, ReadbackState::Idle)
+    }
+
+    /// Records a copy of the first `size` bytes of `source`. Ignored while busy.
+    pub fn copy_buffer(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source: &Buffer,
+        size: u64,
+    ) {
+        if !self.is_idle() {
+            return;
+        }
+        let buffer = self.staging(device, size);
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, size);
+        self.rows = None;
+        self.state = ReadbackState::Copied;
+    }
+
+    /// Records a copy of an `Rgba32Float` texture. Ignored while busy.
+    pub fn copy_texture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) {
+        if !self.is_idle() {
+            return;
+        }
+        let size = texture.size();
+        let bytes_per_row =
+            (size.width * TEXEL_SIZE).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.staging(device, bytes_per_row as u64 * size.height as u64);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        self.rows = Some((size.width, bytes_per_row));
+        self.state = ReadbackState::Copied;
+    }
+
+    /// Starts mapping the copy recorded this frame. Call after the frame was submitted.
+    pub fn submitted(&mut self) {
+        let (ReadbackState::Copied, Some(buffer)) = (&self.state, &self.buffer) else {
+            return;
+        };
+        let result: MapResult = Default::default();
+        let callback_result = result.clone();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |mapped| {
+                *callback_result.lock().unwrap() = Some(mapped);
+            });
+        self.state = ReadbackState::Mapping(result);
+    }
+
+    /// The copied bytes once the GPU is done with them, texture rows without padding.
+    /// Never waits, `None` until then.
+    pub fn take(&mut self, device: &Device) -> Option<Vec<u8>> {
+        let ReadbackState::Mapping(result) = &self.state else {
+            return None;
+        };
+        if let Err(err) = device.poll(wgpu::PollType::Poll) {
+            log::error!("Failed to poll {} readback: {err}", self.label);
+        }
+        let mapped = result.lock().unwrap().take()?;
+        self.state = ReadbackState::Idle;
+        let buffer = self.buffer.as_ref()?;
+        if let Err(err) = mapped {
+            log::error!("Failed to map {} readback: {err}", self.label);
+            return None;
+        }
+        let data = buffer.slice(..).get_mapped_range();
+        let bytes = match self.rows {
+            Some((width, bytes_per_row)) => data
+                .chunks_exact(bytes_per_row as usize)
+                .flat_map(|row| &row[..(width * TEXEL_SIZE) as usize])
+                .copied()
+                .collect(),
+            None => data.to_vec(),
+        };
+        drop(data);
+        buffer.unmap();
+        Some(bytes)
+    }
+
+    fn staging(&mut self, device: &Device, size: u64) -> Buffer {
+        match &self.buffer {
+            Some(buffer) if buffer.size() == size => buffer.clone(),
+            _ => {
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(self.label),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                });
+                self.buffer = Some(buffer.clone());
+                buffer
+            }
+        }
+    }
+}
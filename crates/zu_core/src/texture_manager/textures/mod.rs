@@ -6,7 +6,7 @@ use crate::texture_manager::{
 };
 
 use wgpu::{
-    BindGroup, Device, Sampler, TextureView,
+    BindGroup, Device, Sampler, Texture, TextureView,
 };
 
 pub mod scene_texture;
@@ -20,6 +20,7 @@ pub enum TextureType {
 }
 
 pub trait EngineTexture {
+    fn texture(&self) -> &Texture;
     fn view(&self) -> &TextureView;
     fn bind_group(&self) -> &BindGroup;
     fn compute_bind_group(&self) -> &BindGroup;
@@ -44,6 +45,9 @@ pub enum ManagedTexture {
 }
 
 impl EngineTexture for ManagedTexture {
+    fn texture(&self) -> &Texture {
+        self.as_engine_texture().texture()
+    }
     fn view(&self) -> &TextureView {
         self.as_engine_texture().view()
     }
@@ -164,4 +168,12 @@ impl ManagedTexture {
             None
         }
     }
+
+    pub fn scene_mut(&mut self) -> Option<&mut SceneTexture> {
+        if let ManagedTexture::SceneTexture(scene) = self {
+            Some(scene)
+        } else {
+            None
+        }
+    }
 }
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use glam::{IVec2, Vec2};
+use wgpu::{CommandEncoder, Device, Texture};
+
+use crate::{
+    camera::Camera,
+    history::{copy_region, create_snapshot},
+    lights::Light,
+    texture_manager::{
+        dirty_rect::DirtyRect,
+        textures::{EngineTexture, scene_texture::SceneTexture},
+    },
+};
+
+/// Side length of a world chunk in texels.
+pub const CHUNK_SIZE: u32 = 256;
+
+/// Maps world positions to world texels, y down like the textures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TexelGrid {
+    pub texels_per_unit: f32,
+}
+
+impl TexelGrid {
+    pub fn world_to_texel(&self, world: Vec2) -> Vec2 {
+        Vec2::new(world.x, -world.y) * self.texels_per_unit
+    }
+
+    pub fn texel_to_world(&self, texel: Vec2) -> Vec2 {
+        Vec2::new(texel.x, -texel.y) / self.texels_per_unit
+    }
+}
+
+/// Layer contents split into fixed size chunks, allocated the first time something is
+/// painted on them, and the analytic lights placed in the world.
+///
+/// Only a window of the world, the canvas, is held in the layer textures and lit. It
+/// follows the camera: regions painted on the canvas are saved to their chunks every
+/// frame, and the chunks under the canvas are loaded back when it moves.
+pub struct ChunkedWorld {
+    grid: TexelGrid,
+    /// World texel of the top left canvas texel, as currently loaded.
+    origin: IVec2,
+    /// Where the canvas moves to on the next `take_move`.
+    target: IVec2,
+    width: u32,
+    height: u32,
+    chunks: HashMap<String, HashMap<IVec2, Texture>>,
+    lights: Vec<Light>,
+    /// Never written, loaded wherever there is no chunk yet.
+    empty_chunk: Texture,
+}
+
+impl ChunkedWorld {
+    /// The canvas starts out centered at the world origin and 2 world units tall.
+    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+        let origin = -IVec2::new(width as i32, height as i32) / 2;
+        Self {
+            grid: TexelGrid {
+                texels_per_unit: height as f32 * 0.5,
+            },
+            origin,
+            target: origin,
+            width,
+            height,
+            chunks: HashMap::new(),
+            lights: Vec::new(),
+            empty_chunk: create_snapshot(device, "Empty world chunk", CHUNK_SIZE, CHUNK_SIZE),
+        }
+    }
+
+    pub fn grid(&self) -> TexelGrid {
+        self.grid
+    }
+
+    pub fn origin(&self) -> IVec2 {
+        self.origin
+    }
+
+    /// Origin the canvas is moving to, stamps queued from now on are placed relative to it.
+    pub fn target(&self) -> IVec2 {
+        self.target
+    }
+
+    /// Camera whose view is exactly the canvas, for drawing world space geometry into it.
+    pub fn canvas_camera(&self) -> Camera {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let half = size * 0.5 / self.grid.texels_per_unit;
+        let center = self.grid.texel_to_world(self.origin.as_vec2() + size * 0.5);
+        Camera::new(-half.x, half.x, half.y, -half.y, -1.0, 1.0, 1.0, center)
+    }
+
+    pub fn world_to_canvas(&self, world: Vec2) -> Vec2 {
+        self.grid.world_to_texel(world) - self.origin.as_vec2()
+    }
+
+    /// Canvas texels covered by the world space box `min..max`, `None` if it is off the canvas.
+    pub fn canvas_rect(&self, min: Vec2, max: Vec2) -> Option<DirtyRect> {
+        let a = self.world_to_canvas(min);
+        let b = self.world_to_canvas(max);
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let min = (a.min(b).floor() - 1.0).clamp(Vec2::ZERO, size);
+        let max = (a.max(b).ceil() + 1.0).clamp(Vec2::ZERO, size);
+        let rect = DirtyRect::new(min.x as u32, min.y as u32, max.x as u32, max.y as u32);
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        &mut self.lights
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.values().map(HashMap::len).sum()
+    }
+
+    /// Recenters the canvas on the view once the view plus `margin` texels no longer fits
+    /// in it. The margin shrinks to whatever the canvas size leaves room for.
+    pub fn follow(&mut self, camera: &Camera, margin: u32) {
+        let screen = camera.screen_size / camera.pixels_per_point;
+        let a = camera.screen_to_texel(Vec2::ZERO, self.grid);
+        let b = camera.screen_to_texel(screen, self.grid);
+        let (view_min, view_max) = (a.min(b), a.max(b));
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let margin = Vec2::splat(margin as f32)
+            .min((size - (view_max - view_min)) * 0.5)
+            .max(Vec2::ZERO);
+        let min = self.target.as_vec2();
+        if (view_min - margin).cmpge(min).all() && (view_max + margin).cmple(min + size).all() {
+            return;
+        }
+        self.target = ((view_min + view_max - size) * 0.5).round().as_ivec2();
+    }
+
+    /// Moves the canvas to the target. Returns how far the canvas contents shift, in texels.
+    pub fn take_move(&mut self) -> Option<IVec2> {
+        let delta = self.origin - self.target;
+        self.origin = self.target;
+        (delta != IVec2::ZERO).then_some(delta)
+    }
+
+    /// Changes the canvas size, the world texel at the old top left corner ends up at
+    /// `offset` on the new canvas.
+    pub fn resize(&mut self, width: u32, height: u32, offset: IVec2) {
+        self.width = width;
+        self.height = height;
+        self.origin -= offset;
+        self.target -= offset;
+    }
+
+    /// Copies the regions of `scene` changed since the last save to the chunks of `name`.
+    pub fn save(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        name: &str,
+        scene: &mut SceneTexture,
+    ) {
+        let Some(region) = scene.take_unsaved_rect() else {
+            return;
+        };
+        let min = self.origin + IVec2::new(region.min_x as i32, region.min_y as i32);
+        self.write(device, encoder, name, scene.texture(), region, min);
+    }
+
+    /// Copies `region` of `src` to the chunks of `name`, its top left corner to the world
+    /// texel `min`.
+    pub fn write(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        name: &str,
+        src: &Texture,
+        region: DirtyRect,
+        min: IVec2,
+    ) {
+        // World texel of the top left texel of `src`.
+        let offset = min - IVec2::new(region.min_x as i32, region.min_y as i32);
+        let max = min + IVec2::new(region.width() as i32, region.height() as i32);
+        let chunks = self.chunks.entry(name.to_string()).or_default();
+        for (key, chunk_min, chunk_max) in chunks_between(min, max) {
+            let chunk = chunks
+                .entry(key)
+                .or_insert_with(|| create_snapshot(device, "World chunk", CHUNK_SIZE, CHUNK_SIZE));
+            let src_rect = texel_rect(chunk_min - offset, chunk_max - offset);
+            let dst = chunk_min - key * CHUNK_SIZE as i32;
+            copy_region(encoder, src, src_rect, chunk, dst.x as u32, dst.y as u32);
+        }
+    }
+
+    /// Replaces the whole contents of `scene` with the chunks of `name` under the canvas.
+    pub fn load(&self, encoder: &mut CommandEncoder, name: &str, scene: &mut SceneTexture) {
+        let size = IVec2::new(self.width as i32, self.height as i32);
+        let chunks = self.chunks.get(name);
+        for (key, chunk_min, chunk_max) in chunks_between(self.origin, self.origin + size) {
+            let chunk = chunks
+                .and_then(|chunks| chunks.get(&key))
+                .unwrap_or(&self.empty_chunk);
+            let corner = key * CHUNK_SIZE as i32;
+            let src = texel_rect(chunk_min - corner, chunk_max - corner);
+            let dst = chunk_min - self.origin;
+            copy_region(
+                encoder,
+                chunk,
+                src,
+                scene.texture(),
+                dst.x as u32,
+                dst.y as u32,
+            );
+        }
+        scene.mark_changed(DirtyRect::full(self.width, self.height));
+        // Loaded contents match the chunks already.
+        scene.take_unsaved_rect();
+    }
+
+    /// Drops the chunks of a removed layer.
+    pub fn forget(&mut self, name: &str) {
+        self.chunks.remove(name);
+    }
+}
+
+/// Chunks overlapping the world texels `min..max`, with the overlapping part of each.
+fn chunks_between(min: IVec2, max: IVec2) -> impl Iterator<Item = (IVec2, IVec2, IVec2)> {
+    let size = CHUNK_SIZE as i32;
+    let first = min.div_euclid(IVec2::splat(size));
+    let last = (max - 1).div_euclid(IVec2::splat(size));
+    (first.y..=last.y).flat_map(move |y| {
+        (first.x..=last.x).map(move |x| {
+            let key = IVec2::new(x, y);
+            let corner = key * size;
+            (key, corner.max(min), (corner + size).min(max))
+        })
+    })
+}
+
+pub fn texel_rect(min: IVec2, max: IVec2) -> DirtyRect {
+    DirtyRect::new(min.x as u32, min.y as u32, max.x as u32, max.y as u32)
+}